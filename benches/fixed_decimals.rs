@@ -0,0 +1,152 @@
+//! Throughput of [FourDecimals] arithmetic and parsing.
+//!
+//! The `baseline` benchmarks run the same operations on the former
+//! representation, a `u64` integral part and a `u16` decimal part, for
+//! comparison.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+
+use csv_processing::FourDecimals;
+
+const VALUES: usize = 1000;
+
+fn values() -> Vec<FourDecimals> {
+    (0..VALUES as u64)
+        .map(|i| FourDecimals::new(i * 7919 % 100000, i * 104729 % 10000))
+        .collect()
+}
+
+/// Former representation of [FourDecimals].
+mod baseline {
+    #[derive(Debug, Clone, Copy, Default)]
+    pub struct FourDecimals {
+        pub integer: u64,
+        pub decimal: u16,
+    }
+    impl FourDecimals {
+        pub fn checked_add(self, other: Self) -> Option<Self> {
+            let mut integer = self.integer.checked_add(other.integer)?;
+            let mut decimal = self.decimal + other.decimal;
+            if decimal >= 10000 {
+                integer = integer.checked_add(1)?;
+                decimal -= 10000;
+            }
+            Some(Self { integer, decimal })
+        }
+        pub fn checked_sub(self, other: Self) -> Option<Self> {
+            let mut integer = self.integer.checked_sub(other.integer)?;
+            let decimal = if other.decimal > self.decimal {
+                integer = integer.checked_sub(1)?;
+                10000 - (other.decimal - self.decimal)
+            }
+            else {
+                self.decimal - other.decimal
+            };
+            Some(Self { integer, decimal })
+        }
+        pub fn parse(s: &str) -> Option<Self> {
+            let mut parts = s.split('.');
+            let integer = parts.next()?.parse::<u64>().ok()?;
+            let decimal = parts.next().unwrap_or("0");
+            if decimal.len() > 4 {
+                return None
+            }
+            let digits = decimal.len();
+            let mut decimal = decimal.parse::<u16>().ok()?;
+            for _ in digits..4 {
+                decimal *= 10;
+            }
+            Some(Self { integer, decimal })
+        }
+    }
+    impl std::fmt::Display for FourDecimals {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "{}.{:0>4}", self.integer, self.decimal)
+        }
+    }
+
+    pub fn values() -> Vec<FourDecimals> {
+        (0..super::VALUES as u64)
+            .map(|i| FourDecimals { integer: i * 7919 % 100000, decimal: (i * 104729 % 10000) as u16 })
+            .collect()
+    }
+}
+
+fn add(c: &mut Criterion) {
+    let values = values();
+    let mut group = c.benchmark_group("add");
+    group.throughput(Throughput::Elements(VALUES as u64));
+    group.bench_function("checked_add", |b| b.iter(|| {
+        values.iter().fold(FourDecimals::default(), |sum, value| {
+            black_box(sum.checked_add(*value).unwrap())
+        })
+    }));
+    let baseline = baseline::values();
+    group.bench_function("baseline", |b| b.iter(|| {
+        baseline.iter().fold(baseline::FourDecimals::default(), |sum, value| {
+            black_box(sum.checked_add(*value).unwrap())
+        })
+    }));
+    group.finish();
+}
+
+fn sub(c: &mut Criterion) {
+    let values = values();
+    let mut group = c.benchmark_group("sub");
+    group.throughput(Throughput::Elements(VALUES as u64));
+    group.bench_function("checked_sub", |b| b.iter(|| {
+        values.iter().fold(FourDecimals::default(), |sum, value| {
+            black_box(sum.checked_sub(*value).unwrap())
+        })
+    }));
+    let baseline = baseline::values();
+    let total = baseline.iter().fold(baseline::FourDecimals::default(), |sum, value| {
+        sum.checked_add(*value).unwrap()
+    });
+    group.bench_function("baseline", |b| b.iter(|| {
+        baseline.iter().fold(total, |sum, value| {
+            black_box(sum.checked_sub(*value).unwrap())
+        })
+    }));
+    group.finish();
+}
+
+fn parse(c: &mut Criterion) {
+    let inputs: Vec<String> = values().iter()
+        .map(|value| value.to_string())
+        .collect();
+    let mut group = c.benchmark_group("parse");
+    group.throughput(Throughput::Elements(VALUES as u64));
+    group.bench_function("from_str", |b| b.iter(|| {
+        for input in &inputs {
+            black_box(input.parse::<FourDecimals>().unwrap());
+        }
+    }));
+    group.bench_function("baseline", |b| b.iter(|| {
+        for input in &inputs {
+            black_box(baseline::FourDecimals::parse(input).unwrap());
+        }
+    }));
+    group.finish();
+}
+
+fn format(c: &mut Criterion) {
+    let values = values();
+    let mut group = c.benchmark_group("format");
+    group.throughput(Throughput::Elements(VALUES as u64));
+    group.bench_function("to_string", |b| b.iter(|| {
+        for value in &values {
+            black_box(value.to_string());
+        }
+    }));
+    let baseline = baseline::values();
+    group.bench_function("baseline", |b| b.iter(|| {
+        for value in &baseline {
+            black_box(value.to_string());
+        }
+    }));
+    group.finish();
+}
+
+criterion_group!(benches, add, sub, parse, format);
+criterion_main!(benches);
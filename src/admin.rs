@@ -0,0 +1,65 @@
+//! Administrative operations on client accounts.
+
+use std::time::SystemTime;
+
+use crate::ClientId;
+
+/// Status of a client account, transactions are applied to active accounts only.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccountStatus {
+    /// Transactions are applied.
+    Active,
+    /// Locked after a chargeback.
+    Locked,
+    /// Frozen by an administrator.
+    Frozen,
+    /// Closed by an administrator, for good.
+    Closed,
+}
+impl Default for AccountStatus {
+    fn default() -> Self {
+        AccountStatus::Active
+    }
+}
+
+/// Administrative action on a client account.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AdminAction {
+    /// Reinstate a locked or frozen account.
+    Unlock,
+    /// Freeze an active or locked account.
+    Freeze,
+    /// Close an account that is not closed yet.
+    Close,
+}
+impl AdminAction {
+    /// [AccountStatus] after the action, `None` when not allowed from `status`.
+    pub fn transition(self, status: AccountStatus) -> Option<AccountStatus> {
+        match (self, status) {
+            (AdminAction::Unlock, AccountStatus::Locked | AccountStatus::Frozen) =>
+                Some(AccountStatus::Active),
+            (AdminAction::Freeze, AccountStatus::Active | AccountStatus::Locked) =>
+                Some(AccountStatus::Frozen),
+            (AdminAction::Close, AccountStatus::Active | AccountStatus::Locked | AccountStatus::Frozen) =>
+                Some(AccountStatus::Closed),
+            _ => None,
+        }
+    }
+}
+
+/// Audit record of an applied [AdminAction].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditRecord {
+    /// Client id.
+    pub client: ClientId,
+    /// [AdminAction].
+    pub action: AdminAction,
+    /// Who applied the action.
+    pub operator: String,
+    /// When the action was applied.
+    pub at: SystemTime,
+    /// [AccountStatus] before the action.
+    pub from: AccountStatus,
+    /// [AccountStatus] after the action.
+    pub to: AccountStatus,
+}
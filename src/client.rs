@@ -1,138 +1,458 @@
 //! Type representation of a client.
 
-use std::collections::{HashMap, HashSet};
-use serde::Serialize;
+use std::collections::{HashSet, VecDeque};
 
-use crate::four_decimals::FourDecimals;
-use crate::{Transaction, TransactionType};
+use serde::{Serialize, Serializer};
+
+use crate::fixed_decimals::FixedDecimals;
+use crate::store::{TxStore, TxRecord, TxState, MemoryStore};
+use crate::{Transaction, TransactionType, ValidTransaction, Applied, Rejection};
+use crate::{Policy, Redispute, WithdrawalDisputes, Limits, Retention};
+use crate::{AccountStatus, AdminAction, AdminError, ClientId, TxId};
 
 #[derive(Debug, Serialize, PartialEq, Eq)]
-struct ClientRaw {
-    client: u16,
-    available: FourDecimals,
-    held: FourDecimals,
-    total: FourDecimals,
+struct ClientRaw<const SCALE: u32> {
+    client: ClientId,
+    available: FixedDecimals<SCALE>,
+    held: FixedDecimals<SCALE>,
+    total: FixedDecimals<SCALE>,
     locked: bool,
 }
 
-/// Type representation of a client.
-#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
-#[serde(into = "ClientRaw")]
-pub struct Client {
-    id: u16,
-    available: FourDecimals,
-    held: FourDecimals,
-    locked: bool,
-    tx_amounts: HashMap<u32, FourDecimals>,
-    disputes: HashSet<u32>,
+/// Withdrawal [Limits] of a client and amount withdrawn since the start of the day,
+/// less the withdrawals of the day reversed by a chargeback.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Withdrawals<const SCALE: u32> {
+    limits: Limits<SCALE>,
+    today: FixedDecimals<SCALE>,
+    /// Ids of the withdrawals counted in `today`.
+    today_txs: HashSet<TxId>,
 }
-impl Client {
+
+/// Failure to apply a [Transaction].
+enum Failure<E> {
+    Rejected(Rejection),
+    Store(E),
+}
+impl<E> From<Rejection> for Failure<E> {
+    fn from(rejection: Rejection) -> Self {
+        Failure::Rejected(rejection)
+    }
+}
+
+/// Type representation of a client with `SCALE` decimals balances.
+///
+/// Past transactions are kept for disputes in a [TxStore] `S`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Client<const SCALE: u32 = 4, S = MemoryStore<SCALE>> {
+    id: ClientId,
+    available: FixedDecimals<SCALE>,
+    held: FixedDecimals<SCALE>,
+    status: AccountStatus,
+    /// Withdrawal limits, boxed as most clients have none.
+    withdrawals: Option<Box<Withdrawals<SCALE>>>,
+    /// Retained deposits and withdrawals, by transaction id.
+    tx_amounts: S,
+    /// Lowest transaction id in the [Retention](crate::Retention) `max_age` window.
+    retained_from: TxId,
+    /// Retained transaction ids in arrival order, for the [Retention](crate::Retention)
+    /// `max_entries` eviction, empty without it.
+    arrivals: VecDeque<TxId>,
+    /// One above the highest transaction id evicted for `max_entries`.
+    evicted_below: TxId,
+    /// Disputed transactions, by transaction id, kept outside of the retention window.
+    disputes: S,
+}
+impl<const SCALE: u32> Client<SCALE> {
     /// Create a new [Client].
-    pub fn new(id: u16) -> Self {
+    pub fn new(id: ClientId) -> Self {
+        Self::with_store(id, MemoryStore::default(), MemoryStore::default())
+    }
+    /// Apply a [Transaction] with the default [Policy].
+    pub fn apply(&mut self, transaction: Transaction<SCALE>) -> Result<Applied, Rejection> {
+        self.apply_with(&Policy::default(), transaction)
+    }
+    /// Apply a [Transaction] with a [Policy].
+    pub fn apply_with(&mut self, policy: &Policy, transaction: Transaction<SCALE>) -> Result<Applied, Rejection> {
+        match self.try_apply_with(policy, transaction) {
+            Ok(outcome) => outcome,
+            Err(never) => match never {},
+        }
+    }
+}
+impl<const SCALE: u32, S: TxStore<SCALE>> Client<SCALE, S> {
+    /// Create a new [Client], keeping transactions in empty [TxStore]s.
+    pub fn with_store(id: ClientId, tx_amounts: S, disputes: S) -> Self {
         Self {
             id,
-            available: FourDecimals::default(),
-            held: FourDecimals::default(),
-            locked: false,
-            tx_amounts: HashMap::new(),
-            disputes: HashSet::new(),
+            available: FixedDecimals::default(),
+            held: FixedDecimals::default(),
+            status: AccountStatus::Active,
+            withdrawals: None,
+            tx_amounts,
+            retained_from: TxId::MIN,
+            arrivals: VecDeque::new(),
+            evicted_below: TxId::MIN,
+            disputes,
         }
     }
+    /// Get the [Client] id.
+    pub fn id(&self) -> ClientId {
+        self.id
+    }
+    /// Whether the [Client] is not [AccountStatus::Active].
+    pub fn locked(&self) -> bool {
+        self.status != AccountStatus::Active
+    }
+    /// Get the [Client] [AccountStatus].
+    pub fn status(&self) -> AccountStatus {
+        self.status
+    }
+    /// Apply an [AdminAction], returning the previous [AccountStatus].
+    pub fn admin(&mut self, action: AdminAction) -> Result<AccountStatus, AdminError> {
+        let status = self.status;
+        self.status = action.transition(status)
+            .ok_or(AdminError::NotAllowed { action, status })?;
+        Ok(status)
+    }
     /// Get the [Client] total.
-    pub fn total(&self) -> FourDecimals {
+    pub fn total(&self) -> FixedDecimals<SCALE> {
         self.available + self.held
     }
     /// Get the [Client] available balance.
-    pub fn available(&self) -> FourDecimals {
+    pub fn available(&self) -> FixedDecimals<SCALE> {
         self.available
     }
     /// Get the [Client] held balance.
-    pub fn held(&self) -> FourDecimals {
+    pub fn held(&self) -> FixedDecimals<SCALE> {
         self.held
     }
+    /// Get the [Client] withdrawal [Limits].
+    pub fn limits(&self) -> Limits<SCALE> {
+        self.withdrawals.as_ref().map_or_else(Limits::default, |withdrawals| withdrawals.limits)
+    }
+    /// Set the [Client] withdrawal [Limits].
+    ///
+    /// Withdrawals count towards the daily limit from the first limits set.
+    pub fn set_limits(&mut self, limits: Limits<SCALE>) {
+        match self.withdrawals.as_mut() {
+            Some(withdrawals) => withdrawals.limits = limits,
+            None if limits == Limits::default() => {},
+            None => self.withdrawals = Some(Box::new(Withdrawals {
+                limits,
+                today: FixedDecimals::default(),
+                today_txs: HashSet::new(),
+            })),
+        }
+    }
+    /// Start a new day of the daily withdrawal limit.
+    pub fn start_day(&mut self) {
+        if let Some(withdrawals) = self.withdrawals.as_mut() {
+            withdrawals.today = FixedDecimals::default();
+            withdrawals.today_txs.clear();
+        }
+    }
+    /// Number of retained past transactions.
+    pub fn retained(&self) -> usize {
+        self.tx_amounts.len()
+    }
+    /// [TxState] of a past transaction, `None` when unknown or outside of the retention window.
+    pub fn state(&self, tx: TxId) -> Result<Option<TxState>, S::Error> {
+        if let Some(record) = self.disputes.get(tx)? {
+            return Ok(Some(record.state))
+        }
+        Ok(self.tx_amounts.get(tx)?.map(|record| record.state))
+    }
 
-    /// Apply a [Transaction].
-    pub fn apply(&mut self, transaction: Transaction) {
-        if self.locked {
-            return
+    /// Record a new transaction, first forgetting transactions outside of the retention
+    /// window, so that a failing [TxStore] leaves the transaction unrecorded.
+    ///
+    /// The ids of forgotten transactions, and of this one if not retained, are added to `forgotten`.
+    fn record(&mut self, retention: Retention, tx: TxId, record: TxRecord<SCALE>, forgotten: &mut Vec<TxId>)
+        -> Result<(), Failure<S::Error>>
+    {
+        if let Some(max_age) = retention.max_age {
+            let retained_from = self.retained_from.max(TxId(tx.0.saturating_sub(max_age)));
+            if retained_from > self.retained_from {
+                forgotten.extend(self.tx_amounts.forget_below(retained_from).map_err(Failure::Store)?);
+                self.retained_from = retained_from;
+                // drop forgotten ids once they outnumber the retained ones
+                if self.arrivals.len() > 2 * self.tx_amounts.len() {
+                    self.arrivals.retain(|tx| *tx >= retained_from);
+                }
+            }
+        }
+        if tx < self.retained_from {
+            forgotten.push(tx);
+            return Ok(())
+        }
+        if let Some(max_entries) = retention.max_entries {
+            // evict the earliest received transactions to make room for this one
+            while self.tx_amounts.len() >= max_entries {
+                let oldest = match self.arrivals.front() {
+                    Some(oldest) => *oldest,
+                    None => break,
+                };
+                if self.tx_amounts.remove(oldest).map_err(Failure::Store)?.is_some() {
+                    self.evicted_below = self.evicted_below.max(TxId(oldest.0.saturating_add(1)));
+                    forgotten.push(oldest);
+                }
+                self.arrivals.pop_front();
+            }
+            if max_entries == 0 {
+                self.evicted_below = self.evicted_below.max(TxId(tx.0.saturating_add(1)));
+                forgotten.push(tx);
+                return Ok(())
+            }
         }
 
-        let amount = transaction.amount.unwrap_or_default();
+        self.tx_amounts.insert(tx, record).map_err(Failure::Store)?;
+        if retention.max_entries.is_some() {
+            self.arrivals.push_back(tx);
+        }
+        Ok(())
+    }
+    /// Retained transaction, [Rejection::Expired] below the retention window
+    /// whether or not the id was used, forgotten ids are not kept.
+    fn recorded(&self, tx: TxId) -> Result<TxRecord<SCALE>, Failure<S::Error>> {
+        match self.tx_amounts.get(tx).map_err(Failure::Store)? {
+            Some(record) => Ok(record),
+            None if tx < self.retained_from || tx < self.evicted_below =>
+                Err(Rejection::Expired.into()),
+            None => Err(Rejection::UnknownTransaction.into()),
+        }
+    }
 
-        match transaction.r#type {
-            TransactionType::Deposit => {
-                self.tx_amounts.insert(transaction.tx, amount);
-                self.available = self.available + amount;
+    /// Disputed transaction.
+    fn disputed(&self, tx: TxId) -> Result<TxRecord<SCALE>, Failure<S::Error>> {
+        match self.disputes.get(tx).map_err(Failure::Store)? {
+            Some(record) => Ok(record),
+            None => self.recorded(tx).and(Err(Rejection::NotDisputed.into())),
+        }
+    }
+    /// Whether a dispute of the transaction credits held funds, keeping available funds.
+    fn reverses_credit(policy: &Policy, record: &TxRecord<SCALE>) -> bool {
+        record.r#type == TransactionType::Withdrawal
+            && policy.withdrawal_disputes == WithdrawalDisputes::ReverseCredit
+    }
+    /// Settle the dispute of a transaction.
+    fn settle(&mut self, tx: TxId, state: TxState) -> Result<(), Failure<S::Error>> {
+        self.disputes.remove(tx).map_err(Failure::Store)?;
+        // the transaction may have left the retention window while disputed
+        if let Some(record) = self.tx_amounts.get(tx).map_err(Failure::Store)? {
+            self.tx_amounts.insert(tx, TxRecord { state, ..record }).map_err(Failure::Store)?;
+        }
+        Ok(())
+    }
+
+    /// Apply a [Transaction] with the default [Policy], failing when the [TxStore] fails.
+    pub fn try_apply(&mut self, transaction: Transaction<SCALE>)
+        -> Result<Result<Applied, Rejection>, S::Error>
+    {
+        self.try_apply_with(&Policy::default(), transaction)
+    }
+    /// Apply a [Transaction] with a [Policy], failing when the [TxStore] fails.
+    pub fn try_apply_with(&mut self, policy: &Policy, transaction: Transaction<SCALE>)
+        -> Result<Result<Applied, Rejection>, S::Error>
+    {
+        self.try_apply_known(policy, transaction, false, &mut Vec::new())
+    }
+    /// Apply a [Transaction] with a [Policy], `known` when its id was already used by this client,
+    /// adding the ids of transactions forgotten by the retention to `forgotten`.
+    pub(crate) fn try_apply_known(
+        &mut self,
+        policy: &Policy,
+        transaction: Transaction<SCALE>,
+        known: bool,
+        forgotten: &mut Vec<TxId>,
+    ) -> Result<Result<Applied, Rejection>, S::Error> {
+        match self.apply_stored(policy, transaction, known, forgotten) {
+            Ok(applied) => Ok(Ok(applied)),
+            Err(Failure::Rejected(rejection)) => Ok(Err(rejection)),
+            Err(Failure::Store(error)) => Err(error),
+        }
+    }
+    fn apply_stored(
+        &mut self,
+        policy: &Policy,
+        transaction: Transaction<SCALE>,
+        known: bool,
+        forgotten: &mut Vec<TxId>,
+    ) -> Result<Applied, Failure<S::Error>>
+    {
+        match self.status {
+            AccountStatus::Active => {},
+            AccountStatus::Locked => return Err(Rejection::Locked.into()),
+            AccountStatus::Frozen => return Err(Rejection::Frozen.into()),
+            AccountStatus::Closed => return Err(Rejection::Closed.into()),
+        }
+
+        let transaction = ValidTransaction::try_from(transaction).map_err(Rejection::from)?;
+        let tx = transaction.tx();
+        if let Some(amount) = transaction.amount() {
+            match self.tx_amounts.get(tx).map_err(Failure::Store)? {
+                Some(recorded) if policy.idempotent_replays
+                    && (recorded.r#type, recorded.amount) == (transaction.r#type(), amount) =>
+                    return Ok(Applied::Replayed),
+                None if !known => {},
+                _ => return Err(Rejection::DuplicateTransaction.into()),
+            }
+        }
+
+        match transaction {
+            ValidTransaction::Deposit { amount, .. } => {
+                let available = self.available.checked_add(amount)
+                    .ok_or(Rejection::Overflow)?;
+                available.checked_add(self.held)
+                    .ok_or(Rejection::Overflow)?;
+                self.record(policy.retention, tx, TxRecord { r#type: TransactionType::Deposit, amount, state: TxState::Processed },
+                    forgotten)?;
+                self.available = available;
+                Ok(Applied::Deposited)
             },
-            TransactionType::Withdrawal => {
-                if amount > self.available {
-                    return
+            ValidTransaction::Withdrawal { amount, .. } => {
+                let limits = self.limits();
+                if matches!(limits.max_withdrawal, Some(max) if amount > max) {
+                    return Err(Rejection::WithdrawalLimitExceeded.into())
                 }
-                self.tx_amounts.insert(transaction.tx, amount);
-                self.available = self.available - amount;
-            },
-            TransactionType::Dispute => {
-                if !self.disputes.insert(transaction.tx) {
-                    return
+                let withdrawn_today = match &self.withdrawals {
+                    Some(withdrawals) => withdrawals.today.checked_add(amount)
+                        .ok_or(Rejection::Overflow)?,
+                    None => amount,
                 };
-                let amount = match self.tx_amounts.get(&transaction.tx) {
-                    None => return,
-                    Some(amount) => amount,
+                if matches!(limits.max_daily_withdrawal, Some(max) if withdrawn_today > max) {
+                    return Err(Rejection::DailyLimitExceeded.into())
+                }
+                if amount > self.available.saturating_add(limits.overdraft) {
+                    if limits.overdraft.is_zero() {
+                        return Err(Rejection::InsufficientFunds.into())
+                    }
+                    return Err(Rejection::OverdraftExceeded.into())
+                }
+                let available = self.available.checked_sub(amount)
+                    .ok_or(Rejection::Overflow)?;
+                self.record(policy.retention, tx, TxRecord { r#type: TransactionType::Withdrawal, amount, state: TxState::Processed },
+                    forgotten)?;
+                self.available = available;
+                if let Some(withdrawals) = self.withdrawals.as_mut() {
+                    withdrawals.today = withdrawn_today;
+                    withdrawals.today_txs.insert(tx);
+                }
+                Ok(Applied::Withdrawn)
+            },
+            ValidTransaction::Dispute { .. } => {
+                if self.disputes.get(tx).map_err(Failure::Store)?.is_some() {
+                    return Err(Rejection::AlreadyDisputed.into())
+                }
+                let record = self.recorded(tx)?;
+                if record.r#type == TransactionType::Withdrawal {
+                    match policy.withdrawal_disputes {
+                        WithdrawalDisputes::Ignore => return Ok(Applied::Ignored),
+                        WithdrawalDisputes::Reject => return Err(Rejection::WithdrawalDispute.into()),
+                        WithdrawalDisputes::Hold | WithdrawalDisputes::ReverseCredit => {},
+                    }
+                }
+                match record.state {
+                    TxState::Processed => {},
+                    TxState::Disputed => return Err(Rejection::AlreadyDisputed.into()),
+                    TxState::Resolved if policy.redispute == Redispute::Resolved => {},
+                    TxState::Resolved => return Err(Rejection::AlreadyResolved.into()),
+                    TxState::ChargedBack => return Err(Rejection::AlreadyChargedBack.into()),
+                }
+                let available = if Self::reverses_credit(policy, &record) {
+                    self.available
+                }
+                else {
+                    self.available.checked_sub(record.amount)
+                        .ok_or(Rejection::Overflow)?
                 };
-                self.available = self.available - *amount;
-                self.held = self.held + *amount;
+                let held = self.held.checked_add(record.amount)
+                    .ok_or(Rejection::Overflow)?;
+                available.checked_add(held)
+                    .ok_or(Rejection::Overflow)?;
+                let record = TxRecord { state: TxState::Disputed, ..record };
+                self.tx_amounts.insert(tx, record).map_err(Failure::Store)?;
+                self.disputes.insert(tx, record).map_err(Failure::Store)?;
+                self.available = available;
+                self.held = held;
+                Ok(Applied::Disputed)
             },
-            TransactionType::Resolve => {
-                if self.disputes.take(&transaction.tx).is_none() {
-                    return
+            ValidTransaction::Resolve { .. } => {
+                let record = self.disputed(tx)?;
+                let held = self.held.checked_sub(record.amount)
+                    .ok_or(Rejection::Overflow)?;
+                let available = if Self::reverses_credit(policy, &record) {
+                    self.available
                 }
-                let amount = match self.tx_amounts.get(&transaction.tx) {
-                    None => return,
-                    Some(amount) => amount,
+                else {
+                    self.available.checked_add(record.amount)
+                        .ok_or(Rejection::Overflow)?
                 };
-                self.held = self.held - *amount;
-                self.available = self.available + *amount;
+                self.settle(tx, TxState::Resolved)?;
+                self.held = held;
+                self.available = available;
+                Ok(Applied::Resolved)
             },
-            TransactionType::Chargeback => {
-                if self.disputes.take(&transaction.tx).is_none() {
-                    return
+            ValidTransaction::Chargeback { .. } => {
+                let record = self.disputed(tx)?;
+                let held = self.held.checked_sub(record.amount)
+                    .ok_or(Rejection::Overflow)?;
+                let available = if Self::reverses_credit(policy, &record) {
+                    self.available.checked_add(record.amount)
+                        .ok_or(Rejection::Overflow)?
                 }
-                let amount = match self.tx_amounts.get(&transaction.tx) {
-                    None => return,
-                    Some(amount) => amount,
+                else {
+                    self.available
                 };
-                self.held = self.held - *amount;
-                self.locked = true;
+                self.settle(tx, TxState::ChargedBack)?;
+                self.held = held;
+                self.available = available;
+                self.status = AccountStatus::Locked;
+                // a reversed withdrawal of the day no longer counts towards the daily limit
+                if let (true, Some(withdrawals)) = (Self::reverses_credit(policy, &record), self.withdrawals.as_mut()) {
+                    if withdrawals.today_txs.remove(&tx) {
+                        withdrawals.today = withdrawals.today.saturating_sub(record.amount).max(FixedDecimals::ZERO);
+                    }
+                }
+                Ok(Applied::ChargedBack)
             },
-        };
+        }
     }
 }
-impl Into<ClientRaw> for Client {
-    fn into(self) -> ClientRaw {
-        ClientRaw {
-            client: self.id,
-            available: self.available,
-            held: self.held,
-            total: self.available + self.held,
-            locked: self.locked,
+impl<const SCALE: u32, S> From<&Client<SCALE, S>> for ClientRaw<SCALE> {
+    fn from(client: &Client<SCALE, S>) -> Self {
+        Self {
+            client: client.id,
+            available: client.available,
+            held: client.held,
+            total: client.available + client.held,
+            locked: client.status != AccountStatus::Active,
         }
     }
 }
+impl<const SCALE: u32, S> Serialize for Client<SCALE, S> {
+    fn serialize<Ser: Serializer>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error> {
+        ClientRaw::from(self).serialize(serializer)
+    }
+}
 
 #[cfg(test)]
 pub mod test {
     use super::*;
 
+    use crate::{FourDecimals, TxIdRepr};
     use csv::Writer;
 
     #[test]
     pub fn serialize() {
-        let available = FourDecimals { integer: 1, decimal: 0 };
-        let held = FourDecimals { integer: 2, decimal: 2 };
+        let available = FourDecimals::new(1, 0);
+        let held = FourDecimals::new(2, 2);
 
-        let client = Client {
-            id: 1, available, held, locked: false,
-            tx_amounts: HashMap::new(), disputes: HashSet::new(),
+        let client: Client = Client {
+            id: ClientId(1), available, held, status: AccountStatus::Active,
+            withdrawals: None,
+            tx_amounts: MemoryStore::default(), retained_from: TxId(0),
+            arrivals: VecDeque::new(), evicted_below: TxId(0), disputes: MemoryStore::default(),
         };
 
         let output = Vec::new();
@@ -144,4 +464,396 @@ pub mod test {
         assert_eq!(String::from_utf8_lossy(&output),
            "client,available,held,total,locked\n1,1.0000,2.0002,3.0002,false\n");
     }
+
+    fn transaction(r#type: TransactionType, tx: TxIdRepr, amount: Option<u64>) -> Transaction {
+        Transaction {
+            r#type, client: ClientId(1), tx: TxId(tx),
+            amount: amount.map(|integer| FourDecimals::new(integer, 0)),
+        }
+    }
+
+    #[test]
+    pub fn reject_insufficient_funds() {
+        let mut client = Client::new(ClientId(1));
+        assert_eq!(client.apply(transaction(TransactionType::Deposit, 1, Some(1))),
+            Ok(Applied::Deposited));
+        assert_eq!(client.apply(transaction(TransactionType::Withdrawal, 2, Some(2))),
+            Err(Rejection::InsufficientFunds));
+        assert_eq!(client.available(), FourDecimals::new(1, 0));
+    }
+    #[test]
+    pub fn limits() {
+        let mut client = Client::new(ClientId(1));
+        client.set_limits(Limits {
+            overdraft: FourDecimals::new(5, 0),
+            max_withdrawal: Some(FourDecimals::new(4, 0)),
+            max_daily_withdrawal: Some(FourDecimals::new(7, 0)),
+        });
+        assert_eq!(client.apply(transaction(TransactionType::Deposit, 1, Some(1))),
+            Ok(Applied::Deposited));
+        assert_eq!(client.apply(transaction(TransactionType::Withdrawal, 2, Some(5))),
+            Err(Rejection::WithdrawalLimitExceeded));
+        assert_eq!(client.apply(transaction(TransactionType::Withdrawal, 3, Some(4))),
+            Ok(Applied::Withdrawn));
+        assert_eq!(client.available(), -FourDecimals::new(3, 0));
+        assert_eq!(client.apply(transaction(TransactionType::Withdrawal, 4, Some(4))),
+            Err(Rejection::DailyLimitExceeded));
+        assert_eq!(client.apply(transaction(TransactionType::Withdrawal, 5, Some(3))),
+            Err(Rejection::OverdraftExceeded));
+
+        client.start_day();
+        assert_eq!(client.apply(transaction(TransactionType::Withdrawal, 6, Some(2))),
+            Ok(Applied::Withdrawn));
+        assert_eq!(client.available(), -FourDecimals::new(5, 0));
+        assert_eq!(client.apply(transaction(TransactionType::Withdrawal, 7, Some(1))),
+            Err(Rejection::OverdraftExceeded));
+
+        client.set_limits(Limits::default());
+        assert_eq!(client.apply(transaction(TransactionType::Withdrawal, 8, Some(1))),
+            Err(Rejection::InsufficientFunds));
+    }
+    #[test]
+    pub fn reversed_withdrawal_daily_limit() {
+        let policy = Policy { withdrawal_disputes: WithdrawalDisputes::ReverseCredit, ..Policy::default() };
+        let mut client = Client::new(ClientId(1));
+        client.set_limits(Limits { max_daily_withdrawal: Some(FourDecimals::new(5, 0)), ..Limits::default() });
+        assert_eq!(client.apply_with(&policy, transaction(TransactionType::Deposit, 1, Some(10))),
+            Ok(Applied::Deposited));
+        assert_eq!(client.apply_with(&policy, transaction(TransactionType::Withdrawal, 2, Some(4))),
+            Ok(Applied::Withdrawn));
+        assert_eq!(client.apply_with(&policy, transaction(TransactionType::Withdrawal, 3, Some(4))),
+            Err(Rejection::DailyLimitExceeded));
+        assert_eq!(client.apply_with(&policy, transaction(TransactionType::Dispute, 2, None)),
+            Ok(Applied::Disputed));
+        assert_eq!(client.apply_with(&policy, transaction(TransactionType::Chargeback, 2, None)),
+            Ok(Applied::ChargedBack));
+        client.admin(AdminAction::Unlock).unwrap();
+        assert_eq!(client.apply_with(&policy, transaction(TransactionType::Withdrawal, 4, Some(4))),
+            Ok(Applied::Withdrawn));
+    }
+    #[test]
+    pub fn reversed_yesterday_withdrawal_daily_limit() {
+        let policy = Policy { withdrawal_disputes: WithdrawalDisputes::ReverseCredit, ..Policy::default() };
+        let mut client = Client::new(ClientId(1));
+        client.set_limits(Limits { max_daily_withdrawal: Some(FourDecimals::new(5, 0)), ..Limits::default() });
+        assert_eq!(client.apply_with(&policy, transaction(TransactionType::Deposit, 1, Some(10))),
+            Ok(Applied::Deposited));
+        assert_eq!(client.apply_with(&policy, transaction(TransactionType::Withdrawal, 2, Some(4))),
+            Ok(Applied::Withdrawn));
+        client.start_day();
+        assert_eq!(client.apply_with(&policy, transaction(TransactionType::Withdrawal, 3, Some(4))),
+            Ok(Applied::Withdrawn));
+        assert_eq!(client.apply_with(&policy, transaction(TransactionType::Dispute, 2, None)),
+            Ok(Applied::Disputed));
+        assert_eq!(client.apply_with(&policy, transaction(TransactionType::Chargeback, 2, None)),
+            Ok(Applied::ChargedBack));
+        client.admin(AdminAction::Unlock).unwrap();
+        // yesterday's withdrawal does not make room in today's limit
+        assert_eq!(client.apply_with(&policy, transaction(TransactionType::Withdrawal, 4, Some(2))),
+            Err(Rejection::DailyLimitExceeded));
+        assert_eq!(client.apply_with(&policy, transaction(TransactionType::Withdrawal, 5, Some(1))),
+            Ok(Applied::Withdrawn));
+    }
+    #[test]
+    pub fn reject_unknown_transaction() {
+        let mut client = Client::new(ClientId(1));
+        assert_eq!(client.apply(transaction(TransactionType::Dispute, 1, None)),
+            Err(Rejection::UnknownTransaction));
+        assert_eq!(client.apply(transaction(TransactionType::Deposit, 1, Some(1))),
+            Ok(Applied::Deposited));
+        assert_eq!(client.apply(transaction(TransactionType::Dispute, 1, None)),
+            Ok(Applied::Disputed));
+    }
+    #[test]
+    pub fn reject_dispute_lifecycle() {
+        let mut client = Client::new(ClientId(1));
+        assert_eq!(client.apply(transaction(TransactionType::Deposit, 1, Some(1))),
+            Ok(Applied::Deposited));
+        assert_eq!(client.apply(transaction(TransactionType::Resolve, 1, None)),
+            Err(Rejection::NotDisputed));
+        assert_eq!(client.apply(transaction(TransactionType::Dispute, 1, None)),
+            Ok(Applied::Disputed));
+        assert_eq!(client.apply(transaction(TransactionType::Dispute, 1, None)),
+            Err(Rejection::AlreadyDisputed));
+        assert_eq!(client.apply(transaction(TransactionType::Chargeback, 1, None)),
+            Ok(Applied::ChargedBack));
+        assert_eq!(client.apply(transaction(TransactionType::Deposit, 2, Some(1))),
+            Err(Rejection::Locked));
+    }
+    #[test]
+    pub fn dispute_states() {
+        let mut client = Client::new(ClientId(1));
+        assert_eq!(client.state(TxId(1)), Ok(None));
+        for tx in [1, 2] {
+            assert_eq!(client.apply(transaction(TransactionType::Deposit, tx, Some(1))),
+                Ok(Applied::Deposited));
+        }
+        assert_eq!(client.state(TxId(1)), Ok(Some(TxState::Processed)));
+        assert_eq!(client.apply(transaction(TransactionType::Dispute, 1, None)),
+            Ok(Applied::Disputed));
+        assert_eq!(client.state(TxId(1)), Ok(Some(TxState::Disputed)));
+        assert_eq!(client.apply(transaction(TransactionType::Resolve, 1, None)),
+            Ok(Applied::Resolved));
+        assert_eq!(client.state(TxId(1)), Ok(Some(TxState::Resolved)));
+        assert_eq!(client.apply(transaction(TransactionType::Dispute, 1, None)),
+            Err(Rejection::AlreadyResolved));
+        assert_eq!(client.apply(transaction(TransactionType::Resolve, 1, None)),
+            Err(Rejection::NotDisputed));
+
+        assert_eq!(client.apply(transaction(TransactionType::Dispute, 2, None)),
+            Ok(Applied::Disputed));
+        assert_eq!(client.apply(transaction(TransactionType::Chargeback, 2, None)),
+            Ok(Applied::ChargedBack));
+        assert_eq!(client.state(TxId(2)), Ok(Some(TxState::ChargedBack)));
+        assert_eq!(client.total(), FourDecimals::new(1, 0));
+    }
+    #[test]
+    pub fn redispute_resolved() {
+        let policy = Policy { redispute: Redispute::Resolved, ..Policy::default() };
+        let mut client = Client::new(ClientId(1));
+        assert_eq!(client.apply_with(&policy, transaction(TransactionType::Deposit, 1, Some(1))),
+            Ok(Applied::Deposited));
+        for _ in 0..2 {
+            assert_eq!(client.apply_with(&policy, transaction(TransactionType::Dispute, 1, None)),
+                Ok(Applied::Disputed));
+            assert_eq!(client.apply_with(&policy, transaction(TransactionType::Resolve, 1, None)),
+                Ok(Applied::Resolved));
+        }
+        assert_eq!(client.apply_with(&policy, transaction(TransactionType::Dispute, 1, None)),
+            Ok(Applied::Disputed));
+        assert_eq!(client.held(), FourDecimals::new(1, 0));
+    }
+    #[test]
+    pub fn reject_duplicate_transaction() {
+        let mut client = Client::new(ClientId(1));
+        assert_eq!(client.apply(transaction(TransactionType::Deposit, 1, Some(2))),
+            Ok(Applied::Deposited));
+        assert_eq!(client.apply(transaction(TransactionType::Deposit, 1, Some(2))),
+            Err(Rejection::DuplicateTransaction));
+        assert_eq!(client.apply(transaction(TransactionType::Withdrawal, 1, Some(1))),
+            Err(Rejection::DuplicateTransaction));
+        assert_eq!(client.available(), FourDecimals::new(2, 0));
+
+        let policy = Policy { idempotent_replays: true, ..Policy::default() };
+        let mut client = Client::new(ClientId(1));
+        assert_eq!(client.apply_with(&policy, transaction(TransactionType::Deposit, 1, Some(2))),
+            Ok(Applied::Deposited));
+        assert_eq!(client.apply_with(&policy, transaction(TransactionType::Deposit, 1, Some(2))),
+            Ok(Applied::Replayed));
+        assert_eq!(client.apply_with(&policy, transaction(TransactionType::Deposit, 1, Some(3))),
+            Err(Rejection::DuplicateTransaction));
+        assert_eq!(client.apply_with(&policy, transaction(TransactionType::Withdrawal, 1, Some(2))),
+            Err(Rejection::DuplicateTransaction));
+        assert_eq!(client.available(), FourDecimals::new(2, 0));
+    }
+    #[test]
+    pub fn admin() {
+        let mut client = Client::new(ClientId(1));
+        assert_eq!(client.admin(AdminAction::Unlock),
+            Err(AdminError::NotAllowed { action: AdminAction::Unlock, status: AccountStatus::Active }));
+        assert_eq!(client.admin(AdminAction::Freeze), Ok(AccountStatus::Active));
+        assert_eq!(client.apply(transaction(TransactionType::Deposit, 1, Some(1))),
+            Err(Rejection::Frozen));
+        assert_eq!(client.admin(AdminAction::Unlock), Ok(AccountStatus::Frozen));
+        assert_eq!(client.apply(transaction(TransactionType::Deposit, 1, Some(1))),
+            Ok(Applied::Deposited));
+
+        assert_eq!(client.apply(transaction(TransactionType::Dispute, 1, None)),
+            Ok(Applied::Disputed));
+        assert_eq!(client.apply(transaction(TransactionType::Chargeback, 1, None)),
+            Ok(Applied::ChargedBack));
+        assert_eq!(client.status(), AccountStatus::Locked);
+        assert_eq!(client.admin(AdminAction::Unlock), Ok(AccountStatus::Locked));
+        assert!(!client.locked());
+        assert_eq!(client.apply(transaction(TransactionType::Deposit, 2, Some(1))),
+            Ok(Applied::Deposited));
+
+        assert_eq!(client.admin(AdminAction::Close), Ok(AccountStatus::Active));
+        assert_eq!(client.apply(transaction(TransactionType::Deposit, 3, Some(1))),
+            Err(Rejection::Closed));
+        assert_eq!(client.admin(AdminAction::Unlock),
+            Err(AdminError::NotAllowed { action: AdminAction::Unlock, status: AccountStatus::Closed }));
+        assert!(client.locked());
+    }
+    #[test]
+    pub fn reject_overflow() {
+        let mut client = Client::new(ClientId(1));
+        assert_eq!(client.apply(transaction(TransactionType::Deposit, 1, Some(u64::MAX))),
+            Ok(Applied::Deposited));
+        assert_eq!(client.apply(transaction(TransactionType::Deposit, 2, Some(1))),
+            Err(Rejection::Overflow));
+        assert_eq!(client.available(), FourDecimals::new(u64::MAX, 0));
+    }
+    #[test]
+    pub fn reject_invalid_amount() {
+        let mut client = Client::new(ClientId(1));
+        assert_eq!(client.apply(transaction(TransactionType::Deposit, 1, None)),
+            Err(Rejection::MissingAmount));
+        assert_eq!(client.apply(transaction(TransactionType::Deposit, 1, Some(0))),
+            Err(Rejection::ZeroAmount));
+        assert_eq!(client.apply(transaction(TransactionType::Deposit, 1, Some(1))),
+            Ok(Applied::Deposited));
+        assert_eq!(client.apply(transaction(TransactionType::Dispute, 1, Some(1))),
+            Err(Rejection::UnexpectedAmount));
+        assert_eq!(client.held(), FourDecimals::default());
+    }
+    #[test]
+    pub fn reject_negative_amount() {
+        let mut client = Client::new(ClientId(1));
+        let mut deposit = transaction(TransactionType::Deposit, 1, Some(1));
+        deposit.amount = deposit.amount.map(|amount| -amount);
+        assert_eq!(client.apply(deposit), Err(Rejection::NegativeAmount));
+        assert_eq!(client.total(), FourDecimals::default());
+    }
+    #[test]
+    pub fn chargeback_after_withdrawal() {
+        let mut client = Client::new(ClientId(1));
+        assert_eq!(client.apply(transaction(TransactionType::Deposit, 1, Some(2))),
+            Ok(Applied::Deposited));
+        assert_eq!(client.apply(transaction(TransactionType::Withdrawal, 2, Some(1))),
+            Ok(Applied::Withdrawn));
+        assert_eq!(client.apply(transaction(TransactionType::Dispute, 1, None)),
+            Ok(Applied::Disputed));
+        assert_eq!(client.available(), -FourDecimals::new(1, 0));
+        assert_eq!(client.held(), FourDecimals::new(2, 0));
+        assert_eq!(client.apply(transaction(TransactionType::Chargeback, 1, None)),
+            Ok(Applied::ChargedBack));
+        assert_eq!(client.available(), -FourDecimals::new(1, 0));
+        assert_eq!(client.total(), -FourDecimals::new(1, 0));
+    }
+    fn retaining(max_age: Option<TxIdRepr>, max_entries: Option<usize>) -> (Client, Policy) {
+        let retention = Retention { max_age, max_entries };
+        (Client::new(ClientId(1)), Policy { retention, ..Policy::default() })
+    }
+
+    #[test]
+    pub fn retain_max_age() {
+        let (mut client, policy) = retaining(Some(2), None);
+        for tx in [1, 2, 3, 5] {
+            assert_eq!(client.apply_with(&policy, transaction(TransactionType::Deposit, tx, Some(1))),
+                Ok(Applied::Deposited));
+        }
+        assert_eq!(client.retained(), 2);
+        assert_eq!(client.apply_with(&policy, transaction(TransactionType::Dispute, 2, None)),
+            Err(Rejection::Expired));
+        assert_eq!(client.apply_with(&policy, transaction(TransactionType::Dispute, 4, None)),
+            Err(Rejection::UnknownTransaction));
+        assert_eq!(client.apply_with(&policy, transaction(TransactionType::Dispute, 3, None)),
+            Ok(Applied::Disputed));
+    }
+    #[test]
+    pub fn retain_max_entries() {
+        let (mut client, policy) = retaining(None, Some(2));
+        for tx in [1, 2, 3] {
+            assert_eq!(client.apply_with(&policy, transaction(TransactionType::Deposit, tx, Some(1))),
+                Ok(Applied::Deposited));
+        }
+        assert_eq!(client.retained(), 2);
+        assert_eq!(client.apply_with(&policy, transaction(TransactionType::Dispute, 1, None)),
+            Err(Rejection::Expired));
+        assert_eq!(client.apply_with(&policy, transaction(TransactionType::Dispute, 2, None)),
+            Ok(Applied::Disputed));
+
+        // the dispute outlives the retention window
+        for tx in [4, 5] {
+            assert_eq!(client.apply_with(&policy, transaction(TransactionType::Deposit, tx, Some(1))),
+                Ok(Applied::Deposited));
+        }
+        assert_eq!(client.retained(), 2);
+        assert_eq!(client.apply_with(&policy, transaction(TransactionType::Dispute, 2, None)),
+            Err(Rejection::AlreadyDisputed));
+        assert_eq!(client.apply_with(&policy, transaction(TransactionType::Resolve, 2, None)),
+            Ok(Applied::Resolved));
+        assert_eq!(client.apply_with(&policy, transaction(TransactionType::Resolve, 2, None)),
+            Err(Rejection::Expired));
+        assert_eq!(client.state(TxId(2)), Ok(None));
+        assert_eq!(client.available(), FourDecimals::new(5, 0));
+        assert_eq!(client.held(), FourDecimals::default());
+    }
+    #[test]
+    pub fn retain_unused_ids_below_window() {
+        let (mut client, policy) = retaining(None, Some(1));
+        for tx in [10, 20] {
+            assert_eq!(client.apply_with(&policy, transaction(TransactionType::Deposit, tx, Some(1))),
+                Ok(Applied::Deposited));
+        }
+        // forgotten ids are not kept, an unused id below the evicted one is expired too
+        assert_eq!(client.apply_with(&policy, transaction(TransactionType::Dispute, 5, None)),
+            Err(Rejection::Expired));
+        assert_eq!(client.apply_with(&policy, transaction(TransactionType::Dispute, 15, None)),
+            Err(Rejection::UnknownTransaction));
+    }
+    /// [MemoryStore] failing to forget transactions.
+    #[derive(Debug, Default)]
+    struct ForgetFails(MemoryStore<4>);
+    impl TxStore<4> for ForgetFails {
+        type Error = ();
+
+        fn get(&self, tx: TxId) -> Result<Option<TxRecord>, ()> {
+            self.0.get(tx).map_err(|never| match never {})
+        }
+        fn insert(&mut self, tx: TxId, record: TxRecord) -> Result<(), ()> {
+            self.0.insert(tx, record).map_err(|never| match never {})
+        }
+        fn remove(&mut self, tx: TxId) -> Result<Option<TxRecord>, ()> {
+            self.0.remove(tx).map_err(|never| match never {})
+        }
+        fn len(&self) -> usize {
+            self.0.len()
+        }
+        fn forget_below(&mut self, _tx: TxId) -> Result<Vec<TxId>, ()> {
+            Err(())
+        }
+    }
+
+    #[test]
+    pub fn store_failure_leaves_transaction_unrecorded() {
+        let retention = Retention { max_age: Some(1), max_entries: None };
+        let policy = Policy { retention, ..Policy::default() };
+        let mut client = Client::with_store(ClientId(1), ForgetFails::default(), ForgetFails::default());
+        assert_eq!(client.try_apply_with(&policy, transaction(TransactionType::Deposit, 1, Some(1))), Ok(Ok(Applied::Deposited)));
+        assert_eq!(client.try_apply_with(&policy, transaction(TransactionType::Deposit, 5, Some(1))), Err(()));
+        assert_eq!(client.retained(), 1);
+        assert_eq!(client.state(TxId(5)), Ok(None));
+        assert_eq!(client.available(), FourDecimals::new(1, 0));
+    }
+    #[test]
+    pub fn retain_max_entries_arrival_order() {
+        let (mut client, policy) = retaining(None, Some(2));
+        for tx in [10, 20, 5, 30] {
+            assert_eq!(client.apply_with(&policy, transaction(TransactionType::Deposit, tx, Some(1))),
+                Ok(Applied::Deposited));
+        }
+        // the late low id is evicted after the earlier received ones
+        assert_eq!(client.state(TxId(5)), Ok(Some(TxState::Processed)));
+        assert_eq!(client.state(TxId(30)), Ok(Some(TxState::Processed)));
+        assert_eq!(client.apply_with(&policy, transaction(TransactionType::Dispute, 20, None)),
+            Err(Rejection::Expired));
+
+        let (mut client, policy) = retaining(Some(10), Some(2));
+        for tx in 0..100 {
+            assert_eq!(client.apply_with(&policy, transaction(TransactionType::Deposit, tx, Some(1))),
+                Ok(Applied::Deposited));
+        }
+        assert_eq!(client.retained(), 2);
+        assert!(client.arrivals.len() <= 2);
+    }
+    #[test]
+    pub fn scale() {
+        let mut client = Client::<8>::new(ClientId(1));
+        let deposit = Transaction {
+            r#type: TransactionType::Deposit, client: ClientId(1), tx: TxId(1),
+            amount: Some(FixedDecimals::new(1, 12345678)),
+        };
+        assert_eq!(client.apply(deposit), Ok(Applied::Deposited));
+
+        let output = Vec::new();
+        let mut wtr = Writer::from_writer(output);
+        wtr.serialize(client).unwrap();
+        wtr.flush().unwrap();
+        let output = wtr.into_inner().unwrap();
+
+        assert_eq!(String::from_utf8_lossy(&output),
+           "client,available,held,total,locked\n1,1.12345678,0.00000000,1.12345678,false\n");
+    }
 }
@@ -1,34 +1,457 @@
 use std::collections::HashMap;
+use std::collections::hash_map;
+use std::vec;
+use std::time::SystemTime;
 
-use crate::{Client, Transaction};
+use crate::store::{Storage, MemoryStorage};
+use crate::{Client, Transaction, TransactionType, ValidTransaction, Applied, Rejection, Policy};
+use crate::{AdminAction, AdminError, AuditRecord, Limits, ClientId, TxId};
 
-/// Container of [Client]s.
+/// Owner clients of used deposit and withdrawal transaction ids, kept in memory
+/// unless the [Storage] keeps them ([Storage::OWNERS]).
+#[derive(Debug, Default)]
+pub(crate) struct TxOwners {
+    owners: HashMap<TxId, ClientId>,
+}
+impl TxOwners {
+    /// Use the id of a deposit or withdrawal for its client, check the owner of
+    /// the transaction referenced by a dispute, resolve or chargeback.
+    ///
+    /// Whether the id was already used by the client, rejected when used by another client.
+    pub(crate) fn claim<const SCALE: u32, St: Storage<SCALE>>(
+        &mut self,
+        storage: &St,
+        transaction: &Transaction<SCALE>,
+    ) -> Result<Result<bool, Rejection>, St::Error> {
+        // invalid transactions do not use their id
+        if let Err(error) = ValidTransaction::try_from(*transaction) {
+            return Ok(Err(error.into()))
+        }
+        let Transaction { r#type, client, tx, .. } = *transaction;
+        let owner = self.owner(storage, tx)?;
+        match r#type {
+            TransactionType::Deposit | TransactionType::Withdrawal => {},
+            _ => return Ok(match owner {
+                Some(owner) if owner != client => Err(Rejection::ClientMismatch),
+                _ => Ok(false),
+            }),
+        }
+        match owner {
+            Some(owner) if owner != client => Ok(Err(Rejection::DuplicateTransaction)),
+            Some(_) => Ok(Ok(true)),
+            None if St::OWNERS => storage.claim(tx, client)
+                .map(|claimed| if claimed { Ok(false) } else { Err(Rejection::TxIdOutOfRange) }),
+            None => {
+                self.owners.insert(tx, client);
+                Ok(Ok(false))
+            },
+        }
+    }
+    /// Whether claiming the id of a transaction depends on the id being in use,
+    /// which releasing forgotten ids can change.
+    pub(crate) fn contested<const SCALE: u32, St: Storage<SCALE>>(
+        &self,
+        storage: &St,
+        transaction: &Transaction<SCALE>,
+    ) -> Result<bool, St::Error> {
+        let Transaction { r#type, client, tx, .. } = *transaction;
+        let owner = self.owner(storage, tx)?;
+        Ok(match r#type {
+            TransactionType::Deposit | TransactionType::Withdrawal => owner.is_some(),
+            _ => matches!(owner, Some(owner) if owner != client),
+        })
+    }
+    fn owner<const SCALE: u32, St: Storage<SCALE>>(&self, storage: &St, tx: TxId)
+        -> Result<Option<ClientId>, St::Error>
+    {
+        if St::OWNERS {
+            storage.owner(tx)
+        }
+        else {
+            Ok(self.owners.get(&tx).copied())
+        }
+    }
+    /// Release the ids of transactions forgotten by the [Retention](crate::Retention).
+    pub(crate) fn release(&mut self, forgotten: impl IntoIterator<Item = TxId>) {
+        for tx in forgotten {
+            self.owners.remove(&tx);
+        }
+    }
+}
+
+/// Ordering of [Client]s, ascending, ties ordered by id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClientOrder {
+    /// By client id.
+    Id,
+    /// By total balance.
+    Total,
+    /// By available balance.
+    Available,
+    /// By held balance.
+    Held,
+}
+impl Default for ClientOrder {
+    fn default() -> Self {
+        ClientOrder::Id
+    }
+}
+
+/// Container of [Client]s with `SCALE` decimals balances, sharing one [Policy].
+///
+/// Clients are kept contiguously in order of arrival, with an index by client id,
+/// [Clients::iter] and [IntoIterator] follow the order of arrival,
+/// [Clients::sorted] and [Clients::into_sorted] order them by id, or by balance.
+/// so millions of clients with sparse ids take little more than their own size.
+/// Past transactions of clients are kept in the [TxStore](crate::TxStore)s
+/// of a [Storage] `St`. Deposit and withdrawal ids are unique across clients,
+/// an id is used by the first valid deposit or withdrawal carrying it, even when rejected,
+/// until the [Retention](crate::Retention) forgets its transaction.
+/// Disputes, resolves and chargebacks are checked against the client using the id.
 #[derive(Debug)]
-pub struct Clients {
-    clients: HashMap<u16, Client>,
+pub struct Clients<const SCALE: u32 = 4, St: Storage<SCALE> = MemoryStorage> {
+    policy: Policy,
+    tx_amounts: St,
+    disputes: St,
+    pub(crate) owners: TxOwners,
+    clients: Vec<Client<SCALE, St::Store>>,
+    /// Position of every client in `clients`.
+    index: HashMap<ClientId, u32>,
+    /// Withdrawal limits by client id, also of clients not yet seen.
+    limits: HashMap<ClientId, Limits<SCALE>>,
+    audit: Vec<AuditRecord>,
 }
-impl Clients {
+impl<const SCALE: u32> Clients<SCALE> {
     /// Create new [Clients].
     pub fn new() -> Self {
+        Self::with_policy(Policy::default())
+    }
+    /// Create new [Clients] with a [Policy].
+    pub fn with_policy(policy: Policy) -> Self {
+        Self::with_storage(policy, MemoryStorage, MemoryStorage)
+    }
+    /// Apply a [Transaction] to its [Client].
+    pub fn apply(&mut self, transaction: Transaction<SCALE>) -> Result<Applied, Rejection> {
+        match self.try_apply(transaction) {
+            Ok(outcome) => outcome,
+            Err(never) => match never {},
+        }
+    }
+}
+impl<const SCALE: u32, St: Storage<SCALE>> Clients<SCALE, St> {
+    /// Create new [Clients] with a [Policy], keeping transaction amounts
+    /// and disputes in separate [Storage]s.
+    pub fn with_storage(policy: Policy, tx_amounts: St, disputes: St) -> Self {
         Self {
-            clients: HashMap::new(),
+            policy,
+            tx_amounts,
+            disputes,
+            owners: TxOwners::default(),
+            clients: Vec::new(),
+            index: HashMap::new(),
+            limits: HashMap::new(),
+            audit: Vec::new(),
         }
     }
-    /// Apply a [Transaction].
-    pub fn apply(&mut self, transaction: Transaction) {
+    /// Apply a [Transaction] to its [Client], failing when the [Storage] fails.
+    pub fn try_apply(&mut self, transaction: Transaction<SCALE>)
+        -> Result<Result<Applied, Rejection>, St::Error>
+    {
+        let known = match self.owners.claim(&self.tx_amounts, &transaction)? {
+            Ok(known) => known,
+            Err(rejection) => return Ok(Err(rejection)),
+        };
+        let mut forgotten = Vec::new();
+        let outcome = self.try_apply_known(transaction, known, &mut forgotten);
+        self.owners.release(forgotten);
+        outcome
+    }
+    /// Apply a [Transaction] to its [Client], `known` when its id was already used by the client,
+    /// adding the ids of transactions forgotten by the retention to `forgotten`.
+    pub(crate) fn try_apply_known(
+        &mut self,
+        transaction: Transaction<SCALE>,
+        known: bool,
+        forgotten: &mut Vec<TxId>,
+    ) -> Result<Result<Applied, Rejection>, St::Error> {
         let client_id = transaction.client;
 
-        let client = self.clients
-            .entry(client_id)
-            .or_insert(Client::new(client_id));
+        let position = match self.index.entry(client_id) {
+            hash_map::Entry::Occupied(entry) => *entry.get() as usize,
+            hash_map::Entry::Vacant(entry) => {
+                let mut client = Client::with_store(
+                    client_id,
+                    self.tx_amounts.open(client_id)?,
+                    self.disputes.open(client_id)?,
+                );
+                if let Some(limits) = self.limits.get(&client_id) {
+                    client.set_limits(*limits);
+                }
+                // a u32 position covers more clients than fit in memory
+                entry.insert(self.clients.len() as u32);
+                self.clients.push(client);
+                self.clients.len() - 1
+            },
+        };
 
-        client.apply(transaction)
+        self.clients[position].try_apply_known(&self.policy, transaction, known, forgotten)
+    }
+    /// Apply an [AdminAction] to a [Client] on behalf of `operator`, recording it in the audit log.
+    pub fn admin(&mut self, client: ClientId, action: AdminAction, operator: &str)
+        -> Result<&AuditRecord, AdminError>
+    {
+        let position = *self.index.get(&client).ok_or(AdminError::UnknownClient)?;
+        let client_ref = &mut self.clients[position as usize];
+        let from = client_ref.admin(action)?;
+        self.audit.push(AuditRecord {
+            client,
+            action,
+            operator: operator.to_string(),
+            at: SystemTime::now(),
+            from,
+            to: client_ref.status(),
+        });
+        Ok(&self.audit[self.audit.len() - 1])
+    }
+    /// Set the withdrawal [Limits] of a [Client], applied when it is first seen if unknown.
+    pub fn set_limits(&mut self, client: ClientId, limits: Limits<SCALE>) {
+        self.limits.insert(client, limits);
+        if let Some(position) = self.index.get(&client) {
+            self.clients[*position as usize].set_limits(limits);
+        }
+    }
+    /// Start a new day of the daily withdrawal limits of all [Client]s.
+    pub fn start_day(&mut self) {
+        self.clients.iter_mut().for_each(Client::start_day);
+    }
+    /// [AuditRecord]s of all applied [AdminAction]s, in order.
+    pub fn audit(&self) -> &[AuditRecord] {
+        &self.audit
+    }
+    /// Move all [Client]s of `other` into these [Clients].
+    ///
+    /// A [Client] present in both is replaced by the one from `other`,
+    /// transaction ids used, limits and audit records of `other` are added to these [Clients].
+    pub fn merge(&mut self, mut other: Self) {
+        for client in other.clients {
+            match self.index.entry(client.id()) {
+                hash_map::Entry::Occupied(entry) => self.clients[*entry.get() as usize] = client,
+                hash_map::Entry::Vacant(entry) => {
+                    entry.insert(self.clients.len() as u32);
+                    self.clients.push(client);
+                },
+            }
+        }
+        self.owners.owners.extend(other.owners.owners);
+        self.limits.extend(other.limits);
+        self.audit.append(&mut other.audit);
+    }
+    /// Get a [Client] by id.
+    pub fn get(&self, id: ClientId) -> Option<&Client<SCALE, St::Store>> {
+        self.index.get(&id).map(|position| &self.clients[*position as usize])
+    }
+    /// Number of [Client]s.
+    pub fn len(&self) -> usize {
+        self.clients.len()
+    }
+    /// Whether there are no [Client]s.
+    pub fn is_empty(&self) -> bool {
+        self.clients.is_empty()
+    }
+    /// Iterate over [Client]s in order of arrival, see [Clients::sorted] for id order.
+    pub fn iter(&self) -> impl Iterator<Item = &Client<SCALE, St::Store>> {
+        self.clients.iter()
+    }
+    /// [Client]s ordered by id.
+    pub fn sorted(&self) -> Vec<&Client<SCALE, St::Store>> {
+        let mut clients: Vec<_> = self.clients.iter().collect();
+        clients.sort_unstable_by_key(|client| client.id());
+        clients
+    }
+    /// [Client]s sorted by `order`.
+    pub fn into_sorted(self, order: ClientOrder) -> Vec<Client<SCALE, St::Store>> {
+        let mut clients: Vec<Client<SCALE, St::Store>> = self.into_iter().collect();
+        clients.sort_unstable_by_key(Client::id);
+        match order {
+            ClientOrder::Id => {},
+            ClientOrder::Total => clients.sort_by_key(Client::total),
+            ClientOrder::Available => clients.sort_by_key(Client::available),
+            ClientOrder::Held => clients.sort_by_key(Client::held),
+        }
+        clients
+    }
+}
+impl<const SCALE: u32> Default for Clients<SCALE> {
+    fn default() -> Self {
+        Self::new()
     }
 }
-impl IntoIterator for Clients {
-    type Item = Client;
-    type IntoIter = std::collections::hash_map::IntoValues<u16, Client>;
-    fn into_iter(self) -> std::collections::hash_map::IntoValues<u16, Client> {
-        self.clients.into_values()
+impl<const SCALE: u32, St: Storage<SCALE>> IntoIterator for Clients<SCALE, St> {
+    type Item = Client<SCALE, St::Store>;
+    type IntoIter = vec::IntoIter<Client<SCALE, St::Store>>;
+    /// Iterate over [Client]s in order of arrival, like [Clients::iter].
+    fn into_iter(self) -> vec::IntoIter<Client<SCALE, St::Store>> {
+        self.clients.into_iter()
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    use crate::{FourDecimals, TransactionType, ClientIdRepr, TxIdRepr};
+
+    fn deposit(client: ClientIdRepr, tx: TxIdRepr, integer: u64) -> Transaction {
+        Transaction {
+            r#type: TransactionType::Deposit, client: ClientId(client), tx: TxId(tx),
+            amount: Some(FourDecimals::new(integer, 0)),
+        }
+    }
+    fn ids(clients: &[Client]) -> Vec<ClientIdRepr> {
+        clients.iter().map(|client| client.id().0).collect()
+    }
+
+    fn clients() -> Clients {
+        let mut clients = Clients::new();
+        for (tx, (client, integer)) in [(3, 5), (1, 7), (2, 5), (4, 1)].into_iter().enumerate() {
+            clients.apply(deposit(client, tx as TxIdRepr, integer)).unwrap();
+        }
+        let dispute = Transaction {
+            r#type: TransactionType::Dispute, client: ClientId(1), tx: TxId(1), amount: None,
+        };
+        clients.apply(dispute).unwrap();
+        clients
+    }
+
+    #[test]
+    pub fn iter_in_arrival_order_sorted_by_id() {
+        let clients = clients();
+        assert_eq!(clients.iter().map(Client::id).collect::<Vec<_>>(), [3, 1, 2, 4].map(ClientId));
+        assert_eq!(clients.sorted().into_iter().map(Client::id).collect::<Vec<_>>(), [1, 2, 3, 4].map(ClientId));
+        assert_eq!(ids(&clients.into_iter().collect::<Vec<_>>()), [3, 1, 2, 4]);
+    }
+    #[test]
+    pub fn reject_duplicate_transaction() {
+        let mut clients = clients();
+        assert_eq!(clients.apply(deposit(2, 0, 1)), Err(Rejection::DuplicateTransaction));
+        assert_eq!(clients.apply(deposit(3, 0, 1)), Err(Rejection::DuplicateTransaction));
+        assert_eq!(clients.apply(deposit(5, 1, 1)), Err(Rejection::DuplicateTransaction));
+        assert!(clients.get(ClientId(5)).is_none());
+
+        // ids of rejected transactions are used
+        let withdrawal = Transaction {
+            r#type: TransactionType::Withdrawal, client: ClientId(4), tx: TxId(4),
+            amount: Some(FourDecimals::new(2, 0)),
+        };
+        assert_eq!(clients.apply(withdrawal), Err(Rejection::InsufficientFunds));
+        assert_eq!(clients.apply(deposit(4, 4, 2)), Err(Rejection::DuplicateTransaction));
+    }
+    #[test]
+    pub fn invalid_transaction_keeps_id_unused() {
+        let mut clients = Clients::new();
+        assert_eq!(clients.apply(deposit(1, 5, 0)), Err(Rejection::ZeroAmount));
+        assert_eq!(clients.apply(deposit(1, 5, 10)), Ok(Applied::Deposited));
+
+        let missing = Transaction { amount: None, ..deposit(2, 6, 1) };
+        assert_eq!(clients.apply(missing), Err(Rejection::MissingAmount));
+        assert_eq!(clients.apply(deposit(3, 6, 4)), Ok(Applied::Deposited));
+        assert_eq!(clients.get(ClientId(3)).unwrap().total(), FourDecimals::new(4, 0));
+    }
+    #[test]
+    pub fn reject_client_mismatch() {
+        let mut clients = clients();
+        for r#type in [TransactionType::Dispute, TransactionType::Resolve, TransactionType::Chargeback] {
+            let transaction = Transaction { r#type, client: ClientId(2), tx: TxId(1), amount: None };
+            assert_eq!(clients.apply(transaction), Err(Rejection::ClientMismatch));
+        }
+        let dispute = Transaction {
+            r#type: TransactionType::Dispute, client: ClientId(5), tx: TxId(0), amount: None,
+        };
+        assert_eq!(clients.apply(dispute), Err(Rejection::ClientMismatch));
+        assert!(clients.get(ClientId(5)).is_none());
+
+        let resolve = Transaction {
+            r#type: TransactionType::Resolve, client: ClientId(1), tx: TxId(1), amount: None,
+        };
+        assert_eq!(clients.apply(resolve), Ok(Applied::Resolved));
+    }
+    #[test]
+    pub fn admin_audit() {
+        use crate::AccountStatus;
+
+        let mut clients = clients();
+        assert_eq!(clients.admin(ClientId(9), AdminAction::Freeze, "alice"), Err(AdminError::UnknownClient));
+        let record = clients.admin(ClientId(2), AdminAction::Freeze, "alice").unwrap().clone();
+        assert_eq!((record.client, record.action, record.operator.as_str(), record.from, record.to),
+            (ClientId(2), AdminAction::Freeze, "alice", AccountStatus::Active, AccountStatus::Frozen));
+        assert_eq!(clients.apply(deposit(2, 10, 1)), Err(Rejection::Frozen));
+        assert!(clients.admin(ClientId(2), AdminAction::Freeze, "bob").is_err());
+        clients.admin(ClientId(2), AdminAction::Unlock, "bob").unwrap();
+        assert_eq!(clients.apply(deposit(2, 11, 1)), Ok(Applied::Deposited));
+
+        let audit: Vec<_> = clients.audit().iter()
+            .map(|record| (record.operator.as_str(), record.action))
+            .collect();
+        assert_eq!(audit, [("alice", AdminAction::Freeze), ("bob", AdminAction::Unlock)]);
+        assert!(clients.audit()[0].at <= clients.audit()[1].at);
+    }
+    #[test]
+    pub fn limits() {
+        let withdrawal = |client, tx, integer| Transaction {
+            r#type: TransactionType::Withdrawal, ..deposit(client, tx, integer)
+        };
+        let mut clients = clients();
+        let limits = Limits { max_daily_withdrawal: Some(FourDecimals::new(2, 0)), ..Limits::default() };
+        clients.set_limits(ClientId(1), limits);
+        clients.set_limits(ClientId(7), limits);
+        assert_eq!(clients.get(ClientId(1)).unwrap().limits(), limits);
+        assert_eq!(clients.apply(deposit(7, 20, 5)), Ok(Applied::Deposited));
+        assert_eq!(clients.get(ClientId(7)).unwrap().limits(), limits);
+        assert_eq!(clients.apply(withdrawal(7, 21, 2)), Ok(Applied::Withdrawn));
+        assert_eq!(clients.apply(withdrawal(7, 22, 1)), Err(Rejection::DailyLimitExceeded));
+        clients.start_day();
+        assert_eq!(clients.apply(withdrawal(7, 23, 1)), Ok(Applied::Withdrawn));
+    }
+    #[test]
+    pub fn replays() {
+        let policy = Policy { idempotent_replays: true, ..Policy::default() };
+        let mut clients = Clients::with_policy(policy);
+        assert_eq!(clients.apply(deposit(1, 1, 5)), Ok(Applied::Deposited));
+        assert_eq!(clients.apply(deposit(1, 1, 5)), Ok(Applied::Replayed));
+        assert_eq!(clients.apply(deposit(2, 1, 5)), Err(Rejection::DuplicateTransaction));
+        assert_eq!(clients.get(ClientId(1)).unwrap().total(), FourDecimals::new(5, 0));
+        assert!(clients.get(ClientId(2)).is_none());
+    }
+    #[test]
+    pub fn retention_releases_ids() {
+        let retention = crate::Retention { max_age: None, max_entries: Some(1) };
+        let mut clients = Clients::with_policy(Policy { retention, ..Policy::default() });
+        for tx in 0..1000 {
+            assert_eq!(clients.apply(deposit(1, tx, 1)), Ok(Applied::Deposited));
+        }
+        assert_eq!(clients.owners.owners.len(), 1);
+        assert_eq!(clients.apply(deposit(2, 998, 1)), Ok(Applied::Deposited));
+        assert_eq!(clients.apply(deposit(2, 999, 1)), Err(Rejection::DuplicateTransaction));
+    }
+    #[test]
+    pub fn many_sparse_clients() {
+        let mut clients = Clients::new();
+        // typed by the id representations whatever their width
+        for i in 0..100_000u64 {
+            let client = (i * 7919 % 100_000 * 40_000) as ClientIdRepr;
+            assert_eq!(clients.apply(deposit(client, i as TxIdRepr, 1)), Ok(Applied::Deposited));
+        }
+        assert_eq!(clients.len(), 100_000);
+        assert_eq!(clients.get(ClientId(3_999_960_000)).unwrap().total(), FourDecimals::new(1, 0));
+        assert!(clients.get(ClientId(40_001)).is_none());
+        assert!(clients.sorted().into_iter().map(Client::id)
+            .eq((0..100_000u64).map(|i| ClientId((i * 40_000) as ClientIdRepr))));
+    }
+    #[test]
+    pub fn sorted() {
+        assert_eq!(ids(&clients().into_sorted(ClientOrder::Id)), [1, 2, 3, 4]);
+        assert_eq!(ids(&clients().into_sorted(ClientOrder::Total)), [4, 2, 3, 1]);
+        assert_eq!(ids(&clients().into_sorted(ClientOrder::Available)), [1, 4, 2, 3]);
+        assert_eq!(ids(&clients().into_sorted(ClientOrder::Held)), [2, 3, 4, 1]);
     }
 }
@@ -1,9 +1,30 @@
 //! Custom error types.
 
+use std::convert::Infallible;
 use std::io;
-use csv;
 use thiserror::Error;
 
+/// Categories of [MyError]s, each with a distinct process exit code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCategory {
+    /// Wrong command line arguments.
+    Usage,
+    /// Malformed input file.
+    Input,
+    /// Failure to read or write a file.
+    Io,
+}
+impl ErrorCategory {
+    /// Process exit code, from `sysexits.h`.
+    pub fn exit_code(self) -> u8 {
+        match self {
+            ErrorCategory::Usage => 64,
+            ErrorCategory::Input => 65,
+            ErrorCategory::Io => 74,
+        }
+    }
+}
+
 /// Errors.
 #[allow(missing_docs)]
 #[derive(Error, Debug)]
@@ -18,6 +39,196 @@ pub enum MyError {
         #[from]
         error: csv::Error,
     },
-    #[error("Expecting exactly 1 argument: path to the transactions file.")]
+    #[error("Parse Error : {error}")]
+    Parse {
+        #[from]
+        error: Box<ParseError>,
+    },
+    #[error("Usage: csv-processing [<transactions.csv> | -] [--output <clients.csv>] \
+        [--rejects <rejects.csv>] [--sort id|total|available|held] [--threads <n>] \
+        [--retain-age <txs>] [--retain-entries <n>] [--store <dir>] [--idempotent-replays] \
+        [--redispute never|resolved] [--withdrawal-disputes hold|ignore|reject|reverse-credit] \
+        [--limits <limits.csv>] [--on-error fail|skip] [--max-errors <n>] \
+        [--errors <errors.csv>]")]
     WrongArguments(),
+    #[error("Too many malformed rows : {errors}")]
+    TooManyErrors {
+        errors: u64,
+    },
+    #[error("Negative withdrawal limits of client {client}.")]
+    InvalidLimits {
+        client: crate::ClientId,
+    },
+}
+impl MyError {
+    /// Stable machine readable code.
+    pub fn code(&self) -> &'static str {
+        match self {
+            MyError::Io { .. } => "io",
+            MyError::Csv { .. } => "csv",
+            MyError::Parse { .. } => "parse",
+            MyError::WrongArguments() => "usage",
+            MyError::InvalidLimits { .. } => "invalid_limits",
+            MyError::TooManyErrors { .. } => "too_many_errors",
+        }
+    }
+    /// [ErrorCategory] of the error.
+    pub fn category(&self) -> ErrorCategory {
+        match self {
+            MyError::Io { .. } => ErrorCategory::Io,
+            MyError::Csv { error } if error.is_io_error() => ErrorCategory::Io,
+            MyError::Csv { .. } | MyError::Parse { .. } => ErrorCategory::Input,
+            MyError::WrongArguments() => ErrorCategory::Usage,
+            MyError::InvalidLimits { .. } | MyError::TooManyErrors { .. } => ErrorCategory::Input,
+        }
+    }
+}
+impl From<Infallible> for MyError {
+    fn from(never: Infallible) -> Self {
+        match never {}
+    }
+}
+
+/// Malformed csv record, with its position and the offending field when known.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("{}", self.describe())]
+pub struct ParseError {
+    /// Record number, the header being record 0.
+    pub record: Option<u64>,
+    /// Line number, starting at 1.
+    pub line: Option<u64>,
+    /// Byte offset of the record.
+    pub byte: Option<u64>,
+    /// Name of the offending field.
+    pub field: Option<String>,
+    /// Raw value of the offending field.
+    pub value: Option<String>,
+    /// Error message.
+    pub message: String,
+}
+impl ParseError {
+    /// Describe a csv `error` reading or deserializing a `record` with `headers`.
+    pub fn new(error: &csv::Error, record: Option<&csv::StringRecord>, headers: &csv::StringRecord) -> Self {
+        let position = error.position().or_else(|| record.and_then(csv::StringRecord::position));
+        let (index, message) = match error.kind() {
+            csv::ErrorKind::Deserialize { err, .. } => (err.field(), err.kind().to_string()),
+            csv::ErrorKind::Utf8 { err, .. } => (Some(err.field() as u64), err.to_string()),
+            _ => (None, error.to_string()),
+        };
+        let index = index.map(|index| index as usize);
+        Self {
+            record: position.map(csv::Position::record),
+            line: position.map(csv::Position::line),
+            byte: position.map(csv::Position::byte),
+            field: index.and_then(|index| headers.get(index)).map(str::to_string),
+            value: index.and_then(|index| record?.get(index)).map(str::to_string),
+            message,
+        }
+    }
+    fn describe(&self) -> String {
+        let mut description = String::new();
+        if let Some(record) = self.record {
+            description += &format!("record {} ", record);
+        }
+        if let (Some(line), Some(byte)) = (self.line, self.byte) {
+            description += &format!("(line {}, byte {}) ", line, byte);
+        }
+        if let Some(field) = &self.field {
+            description += &format!("field {} ", field);
+        }
+        if let Some(value) = &self.value {
+            description += &format!("value {:?} ", value);
+        }
+        if !description.is_empty() {
+            description.pop();
+            description += ": ";
+        }
+        description + &self.message
+    }
+}
+
+/// Errors validating a [Transaction](crate::Transaction) into a [ValidTransaction](crate::ValidTransaction).
+#[allow(missing_docs)]
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransactionError {
+    #[error("Deposit or withdrawal without amount.")]
+    MissingAmount,
+    #[error("Dispute, resolve or chargeback with an amount.")]
+    UnexpectedAmount,
+    #[error("Transaction amount is zero.")]
+    ZeroAmount,
+    #[error("Transaction amount is negative.")]
+    NegativeAmount,
+}
+
+/// Errors applying an [AdminAction](crate::AdminAction).
+#[allow(missing_docs)]
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AdminError {
+    #[error("Unknown client.")]
+    UnknownClient,
+    #[error("Cannot {action:?} a {status:?} account.")]
+    NotAllowed {
+        action: crate::AdminAction,
+        status: crate::AccountStatus,
+    },
+}
+impl AdminError {
+    /// Stable machine readable code.
+    pub fn code(&self) -> &'static str {
+        match self {
+            AdminError::UnknownClient => "unknown_client",
+            AdminError::NotAllowed { .. } => "not_allowed",
+        }
+    }
+}
+
+/// Errors parsing or converting to a [FixedDecimals](crate::FixedDecimals).
+#[allow(missing_docs)]
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseDecimalsError {
+    #[error("Empty number.")]
+    Empty,
+    #[error("Unexpected sign '{0}'.")]
+    UnexpectedSign(char),
+    #[error("Invalid character '{0}'.")]
+    InvalidCharacter(char),
+    #[error("Multiple decimal points.")]
+    MultipleDots,
+    #[error("Empty integral part.")]
+    EmptyInteger,
+    #[error("Empty decimal part.")]
+    EmptyFraction,
+    #[error("Invalid thousands separator grouping.")]
+    InvalidSeparator,
+    #[error("Invalid exponent.")]
+    InvalidExponent,
+    #[error("Too many decimals.")]
+    TooManyDecimals,
+    #[error("Number out of range.")]
+    Overflow,
+    #[error("Number is not finite.")]
+    NotFinite,
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    pub fn parse_error_position() {
+        let input = "type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,x,2,1.0\n";
+        let mut rdr = csv::Reader::from_reader(input.as_bytes());
+        let headers = rdr.headers().unwrap().clone();
+        let records: Vec<_> = rdr.records().map(Result::unwrap).collect();
+        let error = records[1].deserialize::<crate::Transaction>(Some(&headers)).unwrap_err();
+        let error = ParseError::new(&error, Some(&records[1]), &headers);
+        assert_eq!((error.record, error.line, error.byte), (Some(2), Some(3), Some(38)));
+        assert_eq!((error.field.as_deref(), error.value.as_deref()), (Some("client"), Some("x")));
+        assert!(error.to_string().starts_with("record 2 (line 3, byte 38) field client value \"x\": "));
+
+        let error = MyError::from(Box::new(error));
+        assert_eq!((error.code(), error.category().exit_code()), ("parse", 65));
+        assert_eq!(MyError::WrongArguments().category(), ErrorCategory::Usage);
+    }
 }
@@ -0,0 +1,971 @@
+//! Type representation of a signed u64 with a fixed number of decimals.
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Sub, SubAssign, Mul, Div, Neg};
+
+use serde::{Deserialize, Deserializer, de};
+use serde::{Serialize, Serializer};
+
+use crate::ParseDecimalsError;
+
+/// Rounding mode of operations that cannot be represented exactly.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Rounding {
+    /// Round to nearest, ties to even (banker's rounding).
+    HalfEven,
+    /// Round to nearest, ties away from zero.
+    HalfUp,
+    /// Round towards negative infinity.
+    Floor,
+    /// Round towards positive infinity.
+    Ceiling,
+    /// Round towards zero.
+    Truncate,
+}
+impl Rounding {
+    /// Round the magnitude of `quotient + remainder / divisor`.
+    fn round(self, negative: bool, quotient: u128, remainder: u128, divisor: u128)
+        -> Option<u128>
+    {
+        if remainder == 0 {
+            return Some(quotient)
+        }
+        // remainder < divisor < 2^127, doubling does not overflow
+        let half = (remainder * 2).cmp(&divisor);
+        let up = match self {
+            Rounding::HalfEven => half == Ordering::Greater
+                || (half == Ordering::Equal && quotient % 2 == 1),
+            Rounding::HalfUp => half != Ordering::Less,
+            Rounding::Floor => negative,
+            Rounding::Ceiling => !negative,
+            Rounding::Truncate => false,
+        };
+        if up { quotient.checked_add(1) } else { Some(quotient) }
+    }
+}
+
+/// `a * b / divisor` rounded, `None` when the result does not fit in u128.
+fn mul_div(a: u128, b: u128, divisor: u128, negative: bool, rounding: Rounding)
+    -> Option<u128>
+{
+    if divisor == 0 {
+        return None
+    }
+    if let Some(product) = a.checked_mul(b) {
+        return rounding.round(negative, product / divisor, product % divisor, divisor)
+    }
+
+    // 256 bit product as (high, low)
+    const HALF: u32 = 64;
+    const MASK: u128 = u64::MAX as u128;
+    let (a_high, a_low) = (a >> HALF, a & MASK);
+    let (b_high, b_low) = (b >> HALF, b & MASK);
+    let low_low = a_low * b_low;
+    let middle = (a_high * b_low) + (low_low >> HALF);
+    let (middle, carry) = middle.overflowing_add(a_low * b_high);
+    let high = a_high * b_high + (middle >> HALF) + ((carry as u128) << HALF);
+    let low = (middle << HALF) | (low_low & MASK);
+
+    // long division, divisor < 2^127 so the remainder never overflows
+    if divisor >> 127 != 0 {
+        return None
+    }
+    let mut quotient: u128 = 0;
+    let mut remainder: u128 = 0;
+    for bit in (0..256).rev() {
+        let next = if bit >= 128 { (high >> (bit - 128)) & 1 } else { (low >> bit) & 1 };
+        remainder = (remainder << 1) | next;
+        let digit = remainder >= divisor;
+        if digit {
+            remainder -= divisor;
+        }
+        if quotient >> 127 != 0 {
+            return None
+        }
+        quotient = (quotient << 1) | digit as u128;
+    }
+
+    rounding.round(negative, quotient, remainder, divisor)
+}
+
+/// Type representation of a signed u64 with `SCALE` fixed decimals.
+///
+/// Stored as a single integer in units of `10^-SCALE`, the magnitude is limited to
+/// `u64::MAX` integral part. `SCALE` is at most 18.
+///
+/// A `u64` integral part with 4 decimals needs 78 bits, an i64 of minor units
+/// would cap balances below 10^15, so the units are an i128. The type is
+/// 16 bytes, the same as the `u64` + `u16` pair it replaces.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Default)]
+// 8 byte alignment keeps `(u32, FixedDecimals)` map entries at 24 bytes, as with
+// the `u64` + `u16` pair, instead of the 32 bytes of a 16 byte aligned i128
+#[repr(C, packed(8))]
+pub struct FixedDecimals<const SCALE: u32> {
+    /// Value in units of `10^-SCALE`.
+    units: i128,
+}
+
+/// Type representation of a signed u64 with 4 decimals.
+pub type FourDecimals = FixedDecimals<4>;
+
+impl<const SCALE: u32> FixedDecimals<SCALE> {
+    /// Number of decimal digits.
+    pub const SCALE: u32 = SCALE;
+    /// `10^SCALE`, one in units of the decimal part.
+    const BASE: u64 = {
+        assert!(SCALE <= 18, "FixedDecimals supports at most 18 decimals.");
+        10u64.pow(SCALE)
+    };
+
+    /// Zero.
+    pub const ZERO: Self = Self::new(0, 0);
+    /// One.
+    pub const ONE: Self = Self::new(1, 0);
+    /// Largest representable value.
+    pub const MAX: Self = Self::new(u64::MAX, Self::BASE - 1);
+    /// Smallest representable value.
+    pub const MIN: Self = Self { units: -Self::MAX.units };
+
+    /// Create a new non-negative [FixedDecimals].
+    ///
+    /// `decimal` is in units of `10^-SCALE` and must be less than `10^SCALE`.
+    pub const fn new(integer: u64, decimal: u64) -> Self {
+        debug_assert!(decimal < Self::BASE, "Decimal part out of range.");
+        Self { units: integer as i128 * Self::BASE as i128 + decimal as i128 }
+    }
+    /// Integral part of the magnitude.
+    pub fn integer(self) -> u64 {
+        (self.units.unsigned_abs() / Self::BASE as u128) as u64
+    }
+    /// Decimal part of the magnitude, in units of `10^-SCALE`.
+    pub fn decimal(self) -> u64 {
+        (self.units.unsigned_abs() % Self::BASE as u128) as u64
+    }
+    /// Whether the value is zero.
+    pub fn is_zero(self) -> bool {
+        self == Self::ZERO
+    }
+    /// Whether the value is strictly less than zero.
+    pub fn is_negative(self) -> bool {
+        self.units < 0
+    }
+    /// Absolute value.
+    pub fn abs(self) -> Self {
+        Self { units: self.units.abs() }
+    }
+
+    /// Value in minor units of `10^-SCALE`, e.g. ten-thousandths for [FourDecimals].
+    pub fn to_minor_units(self) -> i128 {
+        self.units
+    }
+    /// Value from minor units of `10^-SCALE`, `None` when out of range.
+    pub fn from_minor_units(units: i128) -> Option<Self> {
+        Self::from_units(units)
+    }
+
+    /// Value from units of `10^-SCALE`, `None` when out of range.
+    fn from_units(units: i128) -> Option<Self> {
+        if units.unsigned_abs() > Self::MAX.units as u128 {
+            return None
+        }
+        Some(Self { units })
+    }
+    /// Value from a signed magnitude in units of `10^-SCALE`, `None` when out of range.
+    fn from_magnitude(negative: bool, magnitude: u128) -> Option<Self> {
+        let units = i128::try_from(magnitude).ok()?;
+        Self::from_units(if negative { -units } else { units })
+    }
+
+    /// Checked addition, `None` on overflow.
+    pub fn checked_add(self, other: Self) -> Option<Self> {
+        Self::from_units(self.units + other.units)
+    }
+    /// Checked subtraction, `None` on overflow.
+    pub fn checked_sub(self, other: Self) -> Option<Self> {
+        Self::from_units(self.units - other.units)
+    }
+
+    /// Checked multiplication by an integer, `None` on overflow.
+    pub fn checked_mul_int(self, other: i64) -> Option<Self> {
+        let magnitude = self.units.unsigned_abs().checked_mul(other.unsigned_abs() as u128)?;
+        Self::from_magnitude(self.is_negative() != (other < 0), magnitude)
+    }
+    /// Checked division by an integer, `None` on overflow or division by zero.
+    pub fn checked_div_int(self, other: i64, rounding: Rounding) -> Option<Self> {
+        let negative = self.is_negative() != (other < 0);
+        let magnitude = mul_div(self.units.unsigned_abs(), 1, other.unsigned_abs() as u128,
+            negative, rounding)?;
+        Self::from_magnitude(negative, magnitude)
+    }
+    /// Checked multiplication, `None` on overflow.
+    ///
+    /// The exact product is rounded to `SCALE` decimals.
+    pub fn checked_mul<const OTHER: u32>(self, other: FixedDecimals<OTHER>, rounding: Rounding)
+        -> Option<Self>
+    {
+        let negative = self.is_negative() != other.is_negative();
+        let magnitude = mul_div(self.units.unsigned_abs(), other.to_minor_units().unsigned_abs(),
+            FixedDecimals::<OTHER>::BASE as u128, negative, rounding)?;
+        Self::from_magnitude(negative, magnitude)
+    }
+    /// Checked division, `None` on overflow or division by zero.
+    ///
+    /// The exact quotient is rounded to `SCALE` decimals.
+    pub fn checked_div<const OTHER: u32>(self, other: FixedDecimals<OTHER>, rounding: Rounding)
+        -> Option<Self>
+    {
+        let negative = self.is_negative() != other.is_negative();
+        let magnitude = mul_div(self.units.unsigned_abs(), FixedDecimals::<OTHER>::BASE as u128,
+            other.to_minor_units().unsigned_abs(), negative, rounding)?;
+        Self::from_magnitude(negative, magnitude)
+    }
+
+    /// Saturating addition, clamps at [FixedDecimals::MIN] and [FixedDecimals::MAX].
+    pub fn saturating_add(self, other: Self) -> Self {
+        self.checked_add(other).unwrap_or(if other.is_negative() { Self::MIN } else { Self::MAX })
+    }
+    /// Saturating subtraction, clamps at [FixedDecimals::MIN] and [FixedDecimals::MAX].
+    pub fn saturating_sub(self, other: Self) -> Self {
+        self.checked_sub(other).unwrap_or(if other.is_negative() { Self::MAX } else { Self::MIN })
+    }
+}
+impl<const SCALE: u32> fmt::Debug for FixedDecimals<SCALE> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("FixedDecimals").field(&format_args!("{}", self)).finish()
+    }
+}
+impl<const SCALE: u32> Neg for FixedDecimals<SCALE> {
+    type Output = Self;
+    fn neg(self) -> Self {
+        Self { units: -self.units }
+    }
+}
+impl<const SCALE: u32> Add for FixedDecimals<SCALE> {
+    type Output = Self;
+    /// Panics on overflow, see [FixedDecimals::checked_add].
+    fn add(self, other: Self) -> Self {
+        self.checked_add(other).expect("FixedDecimals addition overflow")
+    }
+}
+impl<const SCALE: u32> Sub for FixedDecimals<SCALE> {
+    type Output = Self;
+    /// Panics on overflow, see [FixedDecimals::checked_sub].
+    fn sub(self, other: Self) -> Self {
+        self.checked_sub(other).expect("FixedDecimals subtraction overflow")
+    }
+}
+impl<const SCALE: u32> AddAssign for FixedDecimals<SCALE> {
+    /// Panics on overflow, see [FixedDecimals::checked_add].
+    fn add_assign(&mut self, other: Self) {
+        *self = *self + other;
+    }
+}
+impl<const SCALE: u32> SubAssign for FixedDecimals<SCALE> {
+    /// Panics on overflow, see [FixedDecimals::checked_sub].
+    fn sub_assign(&mut self, other: Self) {
+        *self = *self - other;
+    }
+}
+impl<const SCALE: u32> Sum for FixedDecimals<SCALE> {
+    /// Panics on overflow, see [FixedDecimals::checked_add].
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self::ZERO, Add::add)
+    }
+}
+impl<'a, const SCALE: u32> Sum<&'a Self> for FixedDecimals<SCALE> {
+    /// Panics on overflow, see [FixedDecimals::checked_add].
+    fn sum<I: Iterator<Item = &'a Self>>(iter: I) -> Self {
+        iter.copied().sum()
+    }
+}
+impl<const SCALE: u32> Mul<i64> for FixedDecimals<SCALE> {
+    type Output = Self;
+    /// Panics on overflow, see [FixedDecimals::checked_mul_int].
+    fn mul(self, other: i64) -> Self {
+        self.checked_mul_int(other).expect("FixedDecimals multiplication overflow")
+    }
+}
+impl<const SCALE: u32> Div<i64> for FixedDecimals<SCALE> {
+    type Output = Self;
+    /// Rounds half to even, panics on overflow or division by zero,
+    /// see [FixedDecimals::checked_div_int].
+    fn div(self, other: i64) -> Self {
+        self.checked_div_int(other, Rounding::HalfEven).expect("FixedDecimals division overflow")
+    }
+}
+impl<const SCALE: u32> Mul for FixedDecimals<SCALE> {
+    type Output = Self;
+    /// Rounds half to even, panics on overflow, see [FixedDecimals::checked_mul].
+    fn mul(self, other: Self) -> Self {
+        self.checked_mul(other, Rounding::HalfEven).expect("FixedDecimals multiplication overflow")
+    }
+}
+impl<const SCALE: u32> Div for FixedDecimals<SCALE> {
+    type Output = Self;
+    /// Rounds half to even, panics on overflow or division by zero,
+    /// see [FixedDecimals::checked_div].
+    fn div(self, other: Self) -> Self {
+        self.checked_div(other, Rounding::HalfEven).expect("FixedDecimals division overflow")
+    }
+}
+
+/// Optional extensions of the [FixedDecimals] grammar.
+///
+/// The default configuration accepts only the strict grammar,
+/// see [FixedDecimals::parse_with].
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct ParseConfig {
+    /// Accept a thousands separator in the integral part, e.g. `1,234.5`.
+    ///
+    /// Groups after the first one must be exactly 3 digits.
+    pub thousands_separator: Option<char>,
+    /// Accept a decimal exponent suffix, e.g. `1.5e3` or `25E-2`.
+    pub exponent: bool,
+}
+
+impl<const SCALE: u32> FixedDecimals<SCALE> {
+    /// Parse a [FixedDecimals] from a string.
+    ///
+    /// Strict grammar:
+    ///
+    /// ```text
+    /// number   = [ "-" ] integer [ "." fraction ]
+    /// integer  = digit { digit }
+    /// fraction = digit { digit }   (at most SCALE digits)
+    /// ```
+    ///
+    /// Whitespace, a leading `+` and empty integral or decimal parts (`.5`, `5.`) are
+    /// rejected. [ParseConfig] extends the integral part with a thousands separator and
+    /// appends an optional exponent `( "e" | "E" ) [ "+" | "-" ] digit { digit }`.
+    pub fn parse_with(s: &str, config: &ParseConfig) -> Result<Self, ParseDecimalsError> {
+        if s.is_empty() {
+            return Err(ParseDecimalsError::Empty)
+        }
+
+        // strip sign
+        let (negative, s) = match s.strip_prefix('-') {
+            Some(s) => (true, s),
+            None => (false, s),
+        };
+
+        // split off exponent
+        let (mantissa, exponent) = match s.find(['e', 'E']) {
+            Some(position) if config.exponent =>
+                (&s[..position], parse_exponent(&s[position + 1..])?),
+            _ => (s, 0),
+        };
+
+        // split into integer and decimal
+        let mut parts = mantissa.split('.');
+        let integer = parts.next().unwrap_or_default();
+        let fraction = parts.next();
+        if parts.next().is_some() {
+            return Err(ParseDecimalsError::MultipleDots)
+        }
+        if integer.is_empty() {
+            return Err(ParseDecimalsError::EmptyInteger)
+        }
+        if fraction == Some("") {
+            return Err(ParseDecimalsError::EmptyFraction)
+        }
+
+        // validate digits
+        match config.thousands_separator {
+            None => check_digits(integer)?,
+            Some(separator) => check_separators(integer, separator)?,
+        };
+        let fraction = check_digits(fraction.unwrap_or_default())?;
+
+        // shift the decimal point by the exponent
+        let decimals = fraction.len() as i64 - exponent;
+        if decimals > SCALE as i64 {
+            return Err(ParseDecimalsError::TooManyDecimals)
+        }
+
+        // accumulate units of 10^-SCALE
+        let digits = integer.bytes().chain(fraction.bytes())
+            .filter(u8::is_ascii_digit)
+            .map(|digit| (digit - b'0') as u128)
+            .chain(std::iter::repeat(0).take((SCALE as i64 - decimals) as usize));
+        let mut magnitude: u128 = 0;
+        for digit in digits {
+            magnitude = magnitude.checked_mul(10)
+                .and_then(|magnitude| magnitude.checked_add(digit))
+                .ok_or(ParseDecimalsError::Overflow)?;
+        }
+
+        Self::from_magnitude(negative, magnitude)
+            .ok_or(ParseDecimalsError::Overflow)
+    }
+}
+
+/// Check that `s` consists of ascii digits only.
+fn check_digits(s: &str) -> Result<&str, ParseDecimalsError> {
+    match s.chars().find(|c| !c.is_ascii_digit()) {
+        None => Ok(s),
+        Some(c @ ('+' | '-')) => Err(ParseDecimalsError::UnexpectedSign(c)),
+        Some(c) => Err(ParseDecimalsError::InvalidCharacter(c)),
+    }
+}
+/// Check that `s` consists of ascii digits grouped by thousands separators.
+fn check_separators(s: &str, separator: char) -> Result<&str, ParseDecimalsError> {
+    let groups = s.split(separator).count();
+    for (i, group) in s.split(separator).enumerate() {
+        check_digits(group)?;
+        let valid = match i {
+            0 => !group.is_empty() && (groups == 1 || group.len() <= 3),
+            _ => group.len() == 3,
+        };
+        if !valid {
+            return Err(ParseDecimalsError::InvalidSeparator)
+        }
+    }
+    Ok(s)
+}
+/// Parse a signed decimal exponent, at most 2 digits.
+fn parse_exponent(s: &str) -> Result<i64, ParseDecimalsError> {
+    let (negative, digits) = match s.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, s.strip_prefix('+').unwrap_or(s)),
+    };
+    if digits.is_empty() || digits.len() > 2 || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return Err(ParseDecimalsError::InvalidExponent)
+    }
+    let exponent = digits.parse::<i64>()
+        .map_err(|_| ParseDecimalsError::InvalidExponent)?;
+    Ok(if negative { -exponent } else { exponent })
+}
+
+impl<const SCALE: u32> FromStr for FixedDecimals<SCALE> {
+    type Err = ParseDecimalsError;
+    /// Parse using the strict grammar, see [FixedDecimals::parse_with].
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse_with(s, &ParseConfig::default())
+    }
+}
+
+impl<const SCALE: u32> fmt::Display for FixedDecimals<SCALE> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.is_negative() { "-" } else { "" };
+        match SCALE {
+            0 => write!(f, "{}{}", sign, self.integer()),
+            _ => write!(f, "{}{}.{:0>width$}", sign, self.integer(), self.decimal(),
+                width = SCALE as usize),
+        }
+    }
+}
+
+macro_rules! impl_from_unsigned {
+    ($($t:ty),*) => {$(
+        impl<const SCALE: u32> From<$t> for FixedDecimals<SCALE> {
+            fn from(integer: $t) -> Self {
+                Self::new(integer as u64, 0)
+            }
+        }
+    )*};
+}
+impl_from_unsigned!(u8, u16, u32, u64);
+macro_rules! impl_from_signed {
+    ($($t:ty),*) => {$(
+        impl<const SCALE: u32> From<$t> for FixedDecimals<SCALE> {
+            fn from(integer: $t) -> Self {
+                let value = Self::new(integer.unsigned_abs() as u64, 0);
+                if integer < 0 { -value } else { value }
+            }
+        }
+    )*};
+}
+impl_from_signed!(i8, i16, i32, i64);
+
+impl<const SCALE: u32> TryFrom<f64> for FixedDecimals<SCALE> {
+    type Error = ParseDecimalsError;
+    /// Round half to even to `SCALE` decimals, fails on non-finite or out of range values.
+    fn try_from(value: f64) -> Result<Self, Self::Error> {
+        if !value.is_finite() {
+            return Err(ParseDecimalsError::NotFinite)
+        }
+        format!("{:.*}", SCALE as usize, value).parse()
+    }
+}
+
+impl<'de, const SCALE: u32> Deserialize<'de> for FixedDecimals<SCALE> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(de::Error::custom)
+    }
+}
+
+impl<const SCALE: u32> Serialize for FixedDecimals<SCALE> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    use std::io::Cursor;
+    use csv::{ReaderBuilder, Writer};
+
+    #[test]
+    fn size() {
+        assert_eq!(std::mem::size_of::<FourDecimals>(), 16);
+        assert_eq!(std::mem::size_of::<(u32, FourDecimals)>(), 24);
+    }
+    #[test]
+    fn parts() {
+        let value = -FourDecimals::new(12, 34);
+        assert_eq!((value.integer(), value.decimal(), value.is_negative()), (12, 34, true));
+        assert_eq!(format!("{:?}", value), "FixedDecimals(-12.0034)");
+    }
+
+    #[test]
+    fn add_simple() {
+        let a = FourDecimals::new(1, 2);
+        let b = FourDecimals::new(20, 10);
+        let r = FourDecimals::new(21, 12);
+        assert_eq!(a + b, r);
+    }
+    #[test]
+    fn add_overflow() {
+        let a = FourDecimals::new(1, 6000);
+        let b = FourDecimals::new(1, 9000);
+        let r = FourDecimals::new(3, 5000);
+        assert_eq!(a + b, r);
+    }
+    #[test]
+    fn sub_simple() {
+        let a = FourDecimals::new(21, 12);
+        let b = FourDecimals::new(20, 10);
+        let r = FourDecimals::new(1, 2);
+        assert_eq!(a - b, r);
+    }
+    #[test]
+    fn sub_overflow() {
+        let a = FourDecimals::new(3, 5000);
+        let b = FourDecimals::new(1, 9000);
+        let r = FourDecimals::new(1, 6000);
+        assert_eq!(a - b, r);
+    }
+
+    #[test]
+    fn checked_add_overflow() {
+        let a = FourDecimals::new(u64::MAX, 6000);
+        let b = FourDecimals::new(0, 4000);
+        assert_eq!(a.checked_add(b), None);
+        assert_eq!(a.saturating_add(b), FourDecimals::MAX);
+        assert_eq!(a.checked_add(FourDecimals::default()), Some(a));
+    }
+    #[test]
+    fn checked_sub_negative() {
+        let a = FourDecimals::new(1, 0);
+        let b = FourDecimals::new(0, 1);
+        assert_eq!(b.checked_sub(a), Some(-FourDecimals::new(0, 9999)));
+        assert_eq!(a.checked_sub(b), Some(FourDecimals::new(0, 9999)));
+        assert_eq!(a.checked_sub(a), Some(FourDecimals::default()));
+    }
+    #[test]
+    fn checked_sub_underflow() {
+        let b = FourDecimals::new(0, 1);
+        assert_eq!(FourDecimals::MIN.checked_sub(b), None);
+        assert_eq!(FourDecimals::MIN.saturating_sub(b), FourDecimals::MIN);
+        assert_eq!(FourDecimals::MIN.saturating_add(-b), FourDecimals::MIN);
+    }
+    #[test]
+    fn add_mixed_signs() {
+        let a = -FourDecimals::new(1, 6000);
+        let b = FourDecimals::new(1, 9000);
+        assert_eq!(a + b, FourDecimals::new(0, 3000));
+        assert_eq!(b + a, FourDecimals::new(0, 3000));
+        assert_eq!(a + a, -FourDecimals::new(3, 2000));
+    }
+    #[test]
+    fn ordering() {
+        let a = -FourDecimals::new(2, 0);
+        let b = -FourDecimals::new(1, 5000);
+        let c = FourDecimals::new(0, 0);
+        let d = FourDecimals::new(1, 0);
+        assert!(a < b && b < c && c < d);
+        assert_eq!(-c, c);
+    }
+    #[test]
+    #[should_panic]
+    fn add_panics_on_overflow() {
+        let _ = FourDecimals::MAX + FourDecimals::new(0, 1);
+    }
+
+    #[test]
+    fn mul_int() {
+        let a = FourDecimals::new(1, 2500);
+        assert_eq!(a * 3, FourDecimals::new(3, 7500));
+        assert_eq!(a * -2, -FourDecimals::new(2, 5000));
+        assert_eq!((-a).checked_mul_int(0), Some(FourDecimals::default()));
+        assert_eq!(FourDecimals::MAX.checked_mul_int(2), None);
+    }
+    #[test]
+    fn div_int_rounding() {
+        let a = FourDecimals::new(0, 5);
+        let b = -a;
+        let cases = [
+            (Rounding::HalfEven, FourDecimals::new(0, 2), -FourDecimals::new(0, 2)),
+            (Rounding::HalfUp, FourDecimals::new(0, 3), -FourDecimals::new(0, 3)),
+            (Rounding::Floor, FourDecimals::new(0, 2), -FourDecimals::new(0, 3)),
+            (Rounding::Ceiling, FourDecimals::new(0, 3), -FourDecimals::new(0, 2)),
+            (Rounding::Truncate, FourDecimals::new(0, 2), -FourDecimals::new(0, 2)),
+        ];
+        for (rounding, positive, negative) in cases {
+            assert_eq!(a.checked_div_int(2, rounding), Some(positive));
+            assert_eq!(b.checked_div_int(2, rounding), Some(negative));
+        }
+        assert_eq!(FourDecimals::new(0, 7).checked_div_int(2, Rounding::HalfEven),
+            Some(FourDecimals::new(0, 4)));
+        assert_eq!(a.checked_div_int(0, Rounding::HalfEven), None);
+    }
+    #[test]
+    fn mul_fixed() {
+        let amount = FourDecimals::new(200, 0);
+        let rate = FourDecimals::new(0, 125);
+        assert_eq!(amount * rate, FourDecimals::new(2, 5000));
+        let fee = FourDecimals::new(0, 1).checked_mul(FourDecimals::new(0, 5000), Rounding::HalfEven);
+        assert_eq!(fee, Some(FourDecimals::new(0, 0)));
+        let fee = FourDecimals::new(0, 1).checked_mul(FourDecimals::new(0, 5000), Rounding::HalfUp);
+        assert_eq!(fee, Some(FourDecimals::new(0, 1)));
+        let fx = FixedDecimals::<8>::new(1, 8765432);
+        assert_eq!(amount.checked_mul(fx, Rounding::Truncate), Some(FourDecimals::new(217, 5308)));
+    }
+    #[test]
+    fn mul_fixed_wide() {
+        let a = FixedDecimals::<18>::new(1000, 0);
+        assert_eq!(a * a, FixedDecimals::new(1000000, 0));
+        assert_eq!(FixedDecimals::<18>::MAX.checked_mul(a, Rounding::HalfEven), None);
+    }
+    #[test]
+    fn div_fixed() {
+        let a = FourDecimals::new(1, 0);
+        let b = FourDecimals::new(3, 0);
+        assert_eq!(a / b, FourDecimals::new(0, 3333));
+        assert_eq!(a.checked_div(b, Rounding::Ceiling), Some(FourDecimals::new(0, 3334)));
+        assert_eq!((-a).checked_div(b, Rounding::Floor), Some(-FourDecimals::new(0, 3334)));
+        assert_eq!(a.checked_div(FourDecimals::default(), Rounding::HalfEven), None);
+        let wide = FixedDecimals::<18>::new(1000000, 0);
+        assert_eq!(wide / FixedDecimals::new(1000, 0), FixedDecimals::new(1000, 0));
+    }
+
+    #[test]
+    fn parse_strict() {
+        let ok = [
+            ("0", FourDecimals::new(0, 0)),
+            ("-0", FourDecimals::new(0, 0)),
+            ("007", FourDecimals::new(7, 0)),
+            ("1.5", FourDecimals::new(1, 5000)),
+            ("-1.0005", -FourDecimals::new(1, 5)),
+            ("18446744073709551615.9999", FourDecimals::MAX),
+        ];
+        for (input, output) in ok {
+            assert_eq!(input.parse(), Ok(output), "{}", input);
+        }
+
+        let err = [
+            ("", ParseDecimalsError::Empty),
+            ("1.2.3", ParseDecimalsError::MultipleDots),
+            (".5", ParseDecimalsError::EmptyInteger),
+            ("-.5", ParseDecimalsError::EmptyInteger),
+            ("5.", ParseDecimalsError::EmptyFraction),
+            ("+1", ParseDecimalsError::UnexpectedSign('+')),
+            ("--1", ParseDecimalsError::UnexpectedSign('-')),
+            ("1.-5", ParseDecimalsError::UnexpectedSign('-')),
+            (" 1", ParseDecimalsError::InvalidCharacter(' ')),
+            ("1,000", ParseDecimalsError::InvalidCharacter(',')),
+            ("1e3", ParseDecimalsError::InvalidCharacter('e')),
+            ("1.00001", ParseDecimalsError::TooManyDecimals),
+            ("18446744073709551616", ParseDecimalsError::Overflow),
+        ];
+        for (input, error) in err {
+            assert_eq!(input.parse::<FourDecimals>(), Err(error), "{}", input);
+        }
+    }
+    #[test]
+    fn parse_thousands_separator() {
+        let config = ParseConfig { thousands_separator: Some(','), ..ParseConfig::default() };
+        let parse = |s| FourDecimals::parse_with(s, &config);
+
+        assert_eq!(parse("1,234,567.5"), Ok(FourDecimals::new(1234567, 5000)));
+        assert_eq!(parse("123"), Ok(FourDecimals::new(123, 0)));
+        assert_eq!(parse("1234,567"), Err(ParseDecimalsError::InvalidSeparator));
+        assert_eq!(parse("1,23"), Err(ParseDecimalsError::InvalidSeparator));
+        assert_eq!(parse(",123"), Err(ParseDecimalsError::InvalidSeparator));
+        assert_eq!(parse("1.000,5"), Err(ParseDecimalsError::InvalidCharacter(',')));
+    }
+    #[test]
+    fn parse_exponent() {
+        let config = ParseConfig { exponent: true, ..ParseConfig::default() };
+        let parse = |s| FourDecimals::parse_with(s, &config);
+
+        assert_eq!(parse("1e3"), Ok(FourDecimals::new(1000, 0)));
+        assert_eq!(parse("1.5E+3"), Ok(FourDecimals::new(1500, 0)));
+        assert_eq!(parse("-25e-2"), Ok(-FourDecimals::new(0, 2500)));
+        assert_eq!(parse("1e-4"), Ok(FourDecimals::new(0, 1)));
+        assert_eq!(parse("1.5"), Ok(FourDecimals::new(1, 5000)));
+        assert_eq!(parse("1e-5"), Err(ParseDecimalsError::TooManyDecimals));
+        assert_eq!(parse("1e99"), Err(ParseDecimalsError::Overflow));
+        assert_eq!(parse("1e"), Err(ParseDecimalsError::InvalidExponent));
+        assert_eq!(parse("1e1.5"), Err(ParseDecimalsError::InvalidExponent));
+        assert_eq!(parse("e5"), Err(ParseDecimalsError::EmptyInteger));
+    }
+    #[test]
+    fn display() {
+        assert_eq!(FourDecimals::new(1, 12).to_string(), "1.0012");
+        assert_eq!((-FixedDecimals::<0>::new(12, 0)).to_string(), "-12");
+        assert_eq!(FixedDecimals::<8>::new(0, 1).to_string(), "0.00000001");
+    }
+
+    #[test]
+    fn assign_and_sum() {
+        let mut a = FourDecimals::new(1, 5000);
+        a += FourDecimals::new(2, 5000);
+        assert_eq!(a, FourDecimals::from(4));
+        a -= FourDecimals::from(5u64);
+        assert_eq!(a, FourDecimals::from(-1));
+
+        let values = [FourDecimals::new(0, 1), FourDecimals::ONE, -FourDecimals::new(0, 2)];
+        assert_eq!(values.iter().sum::<FourDecimals>(), FourDecimals::new(0, 9999));
+        assert_eq!(values.into_iter().sum::<FourDecimals>(), FourDecimals::new(0, 9999));
+        assert_eq!(std::iter::empty::<FourDecimals>().sum::<FourDecimals>(), FourDecimals::ZERO);
+    }
+    #[test]
+    fn btree_key() {
+        use std::collections::BTreeMap;
+
+        let mut map = BTreeMap::new();
+        map.insert(FourDecimals::ONE, "one");
+        map.insert(-FourDecimals::ONE, "minus one");
+        map.insert(FourDecimals::ZERO, "zero");
+        assert_eq!(map.into_values().collect::<Vec<_>>(), ["minus one", "zero", "one"]);
+    }
+    #[test]
+    fn minor_units() {
+        assert_eq!(FourDecimals::new(12, 34).to_minor_units(), 120034);
+        assert_eq!((-FourDecimals::new(0, 5)).to_minor_units(), -5);
+        assert_eq!(FourDecimals::from_minor_units(-120034), Some(-FourDecimals::new(12, 34)));
+        assert_eq!(FourDecimals::from_minor_units(FourDecimals::MAX.to_minor_units() + 1), None);
+        assert_eq!(FixedDecimals::<0>::from_minor_units(7), Some(FixedDecimals::new(7, 0)));
+    }
+    #[test]
+    fn try_from_float() {
+        assert_eq!(FourDecimals::try_from(1.5), Ok(FourDecimals::new(1, 5000)));
+        assert_eq!(FourDecimals::try_from(-0.00004), Ok(FourDecimals::ZERO));
+        assert_eq!(FourDecimals::try_from(0.1 + 0.2), Ok(FourDecimals::new(0, 3000)));
+        assert_eq!(FourDecimals::try_from(f64::NAN), Err(ParseDecimalsError::NotFinite));
+        assert_eq!(FourDecimals::try_from(f64::INFINITY), Err(ParseDecimalsError::NotFinite));
+        assert_eq!(FourDecimals::try_from(1e30), Err(ParseDecimalsError::Overflow));
+    }
+
+    #[test]
+    pub fn deserialize_integer() {
+        let input = "1";
+
+        let mut rdr = ReaderBuilder::new()
+            .has_headers(false)
+            .from_reader(Cursor::new(input));
+        let mut iter = rdr.deserialize();
+
+        let result = iter.next().unwrap();
+        let output: FourDecimals = result.unwrap();
+
+        assert_eq!(output, FourDecimals::new(1, 0))
+    }
+    #[test]
+    pub fn deserialize_basic() {
+        let input = "1.0";
+
+        let mut rdr = ReaderBuilder::new()
+            .has_headers(false)
+            .from_reader(Cursor::new(input));
+        let mut iter = rdr.deserialize();
+
+        let result = iter.next().unwrap();
+        let output: FourDecimals = result.unwrap();
+
+        assert_eq!(output, FourDecimals::new(1, 0))
+    }
+    #[test]
+    pub fn deserialize_trailing() {
+        let input = "1.000";
+
+        let mut rdr = ReaderBuilder::new()
+            .has_headers(false)
+            .from_reader(Cursor::new(input));
+        let mut iter = rdr.deserialize();
+
+        let result = iter.next().unwrap();
+        let output: FourDecimals = result.unwrap();
+
+        assert_eq!(output, FourDecimals::new(1, 0))
+    }
+    #[test]
+    pub fn deserialize_pad() {
+        let input = "1.120";
+
+        let mut rdr = ReaderBuilder::new()
+            .has_headers(false)
+            .from_reader(Cursor::new(input));
+        let mut iter = rdr.deserialize();
+
+        let result = iter.next().unwrap();
+        let output: FourDecimals = result.unwrap();
+
+        assert_eq!(output, FourDecimals::new(1, 1200))
+    }
+    #[test]
+    pub fn deserialize_complex() {
+        let input = "12345.1234";
+
+        let mut rdr = ReaderBuilder::new()
+            .has_headers(false)
+            .from_reader(Cursor::new(input));
+        let mut iter = rdr.deserialize();
+
+        let result = iter.next().unwrap();
+        let output: FourDecimals = result.unwrap();
+
+        assert_eq!(output, FourDecimals::new(12345, 1234))
+    }
+    #[test]
+    pub fn deserialize_long() {
+        let input = "12345.123456789";
+
+        let mut rdr = ReaderBuilder::new()
+            .has_headers(false)
+            .from_reader(Cursor::new(input));
+        let mut iter = rdr.deserialize();
+
+        let result = iter.next().unwrap();
+        let result: std::result::Result<FourDecimals, csv::Error> = result;
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    pub fn deserialize_negative() {
+        let input = "-12.5";
+
+        let mut rdr = ReaderBuilder::new()
+            .has_headers(false)
+            .from_reader(Cursor::new(input));
+        let mut iter = rdr.deserialize();
+
+        let result = iter.next().unwrap();
+        let output: FourDecimals = result.unwrap();
+
+        assert_eq!(output, -FourDecimals::new(12, 5000))
+    }
+
+    #[test]
+    pub fn serialize_basic() {
+        let input = FourDecimals::new(1, 0);
+        let output = Vec::new();
+
+        let mut wtr = Writer::from_writer(output);
+        wtr.serialize(input).unwrap();
+        wtr.flush().unwrap();
+        let output = wtr.into_inner().unwrap();
+
+        assert_eq!(String::from_utf8_lossy(&output), "1.0000\n");
+    }
+    #[test]
+    pub fn serialize_pad() {
+        let input = FourDecimals::new(1, 12);
+        let output = Vec::new();
+
+        let mut wtr = Writer::from_writer(output);
+        wtr.serialize(input).unwrap();
+        wtr.flush().unwrap();
+        let output = wtr.into_inner().unwrap();
+
+        assert_eq!(String::from_utf8_lossy(&output), "1.0012\n");
+    }
+    #[test]
+    pub fn serialize_complex() {
+        let input = FourDecimals::new(12345, 1234);
+        let output = Vec::new();
+
+        let mut wtr = Writer::from_writer(output);
+        wtr.serialize(input).unwrap();
+        wtr.flush().unwrap();
+        let output = wtr.into_inner().unwrap();
+
+        assert_eq!(String::from_utf8_lossy(&output), "12345.1234\n");
+    }
+    #[test]
+    pub fn serialize_negative() {
+        let input = -FourDecimals::new(12, 5000);
+        let output = Vec::new();
+
+        let mut wtr = Writer::from_writer(output);
+        wtr.serialize(input).unwrap();
+        wtr.flush().unwrap();
+        let output = wtr.into_inner().unwrap();
+
+        assert_eq!(String::from_utf8_lossy(&output), "-12.5000\n");
+    }
+
+    #[test]
+    pub fn deserialize_scale() {
+        let input = "1.5,1.12345678,12";
+
+        let mut rdr = ReaderBuilder::new()
+            .has_headers(false)
+            .from_reader(Cursor::new(input));
+        let mut iter = rdr.deserialize();
+
+        let result = iter.next().unwrap();
+        let output: (FixedDecimals<8>, FixedDecimals<8>, FixedDecimals<0>) = result.unwrap();
+
+        assert_eq!(output, (FixedDecimals::new(1, 50000000),
+            FixedDecimals::new(1, 12345678), FixedDecimals::new(12, 0)))
+    }
+    #[test]
+    pub fn deserialize_scale_too_many_decimals() {
+        let input = "1.5";
+
+        let mut rdr = ReaderBuilder::new()
+            .has_headers(false)
+            .from_reader(Cursor::new(input));
+        let mut iter = rdr.deserialize();
+
+        let result = iter.next().unwrap();
+        let result: std::result::Result<FixedDecimals<0>, csv::Error> = result;
+
+        assert!(result.is_err());
+    }
+    #[test]
+    pub fn serialize_scale() {
+        let input = (FixedDecimals::<8>::new(1, 12), -FixedDecimals::<0>::new(12, 0));
+        let output = Vec::new();
+
+        let mut wtr = Writer::from_writer(output);
+        wtr.serialize(input).unwrap();
+        wtr.flush().unwrap();
+        let output = wtr.into_inner().unwrap();
+
+        assert_eq!(String::from_utf8_lossy(&output), "1.00000012,-12\n");
+    }
+    #[test]
+    fn add_scale() {
+        let a = FixedDecimals::<8>::new(1, 60000000);
+        let b = FixedDecimals::<8>::new(1, 90000000);
+        assert_eq!(a + b, FixedDecimals::new(3, 50000000));
+        assert_eq!(FixedDecimals::<0>::new(1, 0) - FixedDecimals::new(3, 0),
+            -FixedDecimals::new(2, 0));
+    }
+}
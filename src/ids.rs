@@ -0,0 +1,96 @@
+//! Strongly typed client and transaction ids.
+//!
+//! The width of the ids is chosen at compile time, client ids are `u32` by default
+//! and `u64` with the `client-id-u64` feature, transaction ids are `u32` by default
+//! and `u64` with the `tx-id-u64` feature.
+
+use std::fmt;
+use std::num::ParseIntError;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+/// Integer representation of a [ClientId].
+#[cfg(not(feature = "client-id-u64"))]
+pub type ClientIdRepr = u32;
+/// Integer representation of a [ClientId].
+#[cfg(feature = "client-id-u64")]
+pub type ClientIdRepr = u64;
+
+/// Integer representation of a [TxId].
+#[cfg(not(feature = "tx-id-u64"))]
+pub type TxIdRepr = u32;
+/// Integer representation of a [TxId].
+#[cfg(feature = "tx-id-u64")]
+pub type TxIdRepr = u64;
+
+macro_rules! id {
+    ($(#[$attr:meta])* $name:ident($repr:ty)) => {
+        $(#[$attr])*
+        #[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+        #[serde(transparent)]
+        pub struct $name(pub $repr);
+        impl $name {
+            /// Smallest id.
+            pub const MIN: Self = Self(<$repr>::MIN);
+            /// Largest id.
+            pub const MAX: Self = Self(<$repr>::MAX);
+        }
+        impl From<$repr> for $name {
+            fn from(id: $repr) -> Self {
+                Self(id)
+            }
+        }
+        impl From<$name> for $repr {
+            fn from(id: $name) -> Self {
+                id.0
+            }
+        }
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                self.0.fmt(f)
+            }
+        }
+        impl FromStr for $name {
+            type Err = ParseIntError;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                s.parse().map(Self)
+            }
+        }
+    };
+}
+
+id! {
+    /// Client id, serialized as a plain integer.
+    ClientId(ClientIdRepr)
+}
+id! {
+    /// Transaction id, serialized as a plain integer.
+    TxId(TxIdRepr)
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    pub fn display_from_str() {
+        assert_eq!("42".parse(), Ok(ClientId(42)));
+        assert_eq!(ClientId(42).to_string(), "42");
+        assert_eq!(TxId::MAX.to_string().parse(), Ok(TxId::MAX));
+        assert!("-1".parse::<TxId>().is_err());
+        assert!(format!("{}0", ClientId::MAX).parse::<ClientId>().is_err());
+    }
+    #[test]
+    pub fn serde_transparent() {
+        let mut wtr = csv::WriterBuilder::new().has_headers(false).from_writer(vec![]);
+        wtr.serialize((ClientId(1), TxId(2))).unwrap();
+        let output = String::from_utf8(wtr.into_inner().unwrap()).unwrap();
+        assert_eq!(output, "1,2\n");
+
+        let mut rdr = csv::ReaderBuilder::new().has_headers(false).from_reader(output.as_bytes());
+        let ids: (ClientId, TxId) = rdr.deserialize().next().unwrap().unwrap();
+        assert_eq!(ids, (ClientId(1), TxId(2)));
+    }
+}
@@ -5,17 +5,35 @@
 
 //! CSV processor.
 
-mod four_decimals;
-pub use four_decimals::FourDecimals;
+mod fixed_decimals;
+pub use fixed_decimals::{FixedDecimals, FourDecimals, Rounding, ParseConfig};
+
+mod ids;
+pub use ids::{ClientId, TxId, ClientIdRepr, TxIdRepr};
 
 mod transaction;
-pub use transaction::{Transaction, TransactionType};
+pub use transaction::{Transaction, TransactionType, ValidTransaction};
+
+mod policy;
+pub use policy::{Policy, Retention, Redispute, WithdrawalDisputes, Limits};
+
+mod outcome;
+pub use outcome::{Applied, Rejection};
+
+mod store;
+pub use store::{TxStore, TxRecord, TxState, Storage, MemoryStore, MemoryStorage, DiskStore, DiskStorage};
+
+mod admin;
+pub use admin::{AccountStatus, AdminAction, AuditRecord};
 
 mod client;
 pub use client::Client;
 
 mod errors;
-pub use errors::MyError;
+pub use errors::{MyError, ErrorCategory, ParseError, ParseDecimalsError, TransactionError, AdminError};
 
 mod clients;
-pub use clients::Clients;
+pub use clients::{Clients, ClientOrder};
+
+mod sharded;
+pub use sharded::ShardedClients;
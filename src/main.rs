@@ -1,43 +1,481 @@
 use std::env;
-use std::path::Path;
+use std::path::PathBuf;
 use std::fs::File;
-use std::io::stdout;
-use csv;
+use std::io::{self, Read, Write};
+use std::process::ExitCode;
+use serde::{Deserialize, Serialize};
 
-use csv_processing::{Transaction, Clients, MyError};
+use csv_processing::{Transaction, TransactionType, FourDecimals, Rejection, MyError, ParseError};
+use csv_processing::{Client, Clients, ClientOrder, ShardedClients, ClientId, TxId};
+use csv_processing::{Policy, Redispute, WithdrawalDisputes, Limits};
+use csv_processing::{Storage, TxStore, MemoryStorage, DiskStorage};
 
-fn main() -> Result<(), MyError> {
-    let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        return Err(MyError::WrongArguments())
+/// Command line arguments.
+struct Args {
+    /// Input path, stdin when `None`.
+    input: Option<PathBuf>,
+    /// Output path, stdout when `None`.
+    output: Option<PathBuf>,
+    rejects: Option<PathBuf>,
+    sort: ClientOrder,
+    /// Worker threads, sequential processing when 1.
+    threads: usize,
+    policy: Policy,
+    /// Directory of the disk storage files, transactions kept in memory when `None`.
+    store: Option<PathBuf>,
+    /// Path of the client withdrawal limits.
+    limits: Option<PathBuf>,
+    on_error: ErrorMode,
+    /// Path of the report of skipped malformed rows.
+    errors: Option<PathBuf>,
+}
+impl Args {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, MyError> {
+        let mut input = None;
+        let mut output = None;
+        let mut rejects = None;
+        let mut sort = ClientOrder::default();
+        let mut threads = 1;
+        let mut policy = Policy::default();
+        let mut store = None;
+        let mut limits = None;
+        let mut on_error = None;
+        let mut max_errors = None;
+        let mut errors = None;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--output" => {
+                    let path = args.next().ok_or(MyError::WrongArguments())?;
+                    output = Some(PathBuf::from(path));
+                },
+                "--rejects" => {
+                    let path = args.next().ok_or(MyError::WrongArguments())?;
+                    rejects = Some(PathBuf::from(path));
+                },
+                "--sort" => {
+                    sort = match args.next().as_deref() {
+                        Some("id") => ClientOrder::Id,
+                        Some("total") => ClientOrder::Total,
+                        Some("available") => ClientOrder::Available,
+                        Some("held") => ClientOrder::Held,
+                        _ => return Err(MyError::WrongArguments()),
+                    };
+                },
+                "--threads" => {
+                    threads = args.next()
+                        .and_then(|threads| threads.parse().ok())
+                        .filter(|threads| *threads > 0)
+                        .ok_or(MyError::WrongArguments())?;
+                },
+                "--retain-age" => {
+                    policy.retention.max_age = Some(args.next()
+                        .and_then(|age| age.parse().ok())
+                        .ok_or(MyError::WrongArguments())?);
+                },
+                "--retain-entries" => {
+                    policy.retention.max_entries = Some(args.next()
+                        .and_then(|entries| entries.parse().ok())
+                        .ok_or(MyError::WrongArguments())?);
+                },
+                "--idempotent-replays" => policy.idempotent_replays = true,
+                "--redispute" => {
+                    policy.redispute = match args.next().as_deref() {
+                        Some("never") => Redispute::Never,
+                        Some("resolved") => Redispute::Resolved,
+                        _ => return Err(MyError::WrongArguments()),
+                    };
+                },
+                "--withdrawal-disputes" => {
+                    policy.withdrawal_disputes = match args.next().as_deref() {
+                        Some("hold") => WithdrawalDisputes::Hold,
+                        Some("ignore") => WithdrawalDisputes::Ignore,
+                        Some("reject") => WithdrawalDisputes::Reject,
+                        Some("reverse-credit") => WithdrawalDisputes::ReverseCredit,
+                        _ => return Err(MyError::WrongArguments()),
+                    };
+                },
+                "--store" => {
+                    let path = args.next().ok_or(MyError::WrongArguments())?;
+                    store = Some(PathBuf::from(path));
+                },
+                "--limits" => {
+                    let path = args.next().ok_or(MyError::WrongArguments())?;
+                    limits = Some(PathBuf::from(path));
+                },
+                "--on-error" => {
+                    on_error = match args.next().as_deref() {
+                        Some("fail") => Some(false),
+                        Some("skip") => Some(true),
+                        _ => return Err(MyError::WrongArguments()),
+                    };
+                },
+                "--max-errors" => {
+                    max_errors = Some(args.next()
+                        .and_then(|max| max.parse().ok())
+                        .ok_or(MyError::WrongArguments())?);
+                },
+                "--errors" => {
+                    let path = args.next().ok_or(MyError::WrongArguments())?;
+                    errors = Some(PathBuf::from(path));
+                },
+                _ if input.is_some() => return Err(MyError::WrongArguments()),
+                "-" => input = Some(None),
+                _ => input = Some(Some(PathBuf::from(arg))),
+            }
+        }
+
+        // the disk storage does not support retention
+        if store.is_some() && policy.retention != Default::default() {
+            return Err(MyError::WrongArguments())
+        }
+        // a maximum count of errors implies skipping, and conflicts with failing
+        let on_error = match (on_error, max_errors) {
+            (Some(false), Some(_)) => return Err(MyError::WrongArguments()),
+            (Some(false), None) | (None, None) => ErrorMode::Fail,
+            (Some(true), max) | (None, max @ Some(_)) => ErrorMode::Skip { max },
+        };
+
+        Ok(Self {
+            input: input.flatten(),
+            output,
+            rejects,
+            sort,
+            threads,
+            policy,
+            store,
+            limits,
+            on_error,
+            errors,
+        })
     }
+}
 
-    let path = Path::new(&args[1]);
+/// Handling of malformed input rows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ErrorMode {
+    /// Abort on the first malformed row.
+    Fail,
+    /// Skip malformed rows, aborting when more than `max` are skipped.
+    Skip { max: Option<u64> },
+}
+impl Default for ErrorMode {
+    fn default() -> Self {
+        ErrorMode::Fail
+    }
+}
 
-    process(&path)
+/// Record of a malformed input row that was skipped.
+#[derive(Debug, Serialize)]
+struct Skipped {
+    record: Option<u64>,
+    line: Option<u64>,
+    byte: Option<u64>,
+    field: Option<String>,
+    value: Option<String>,
+    error: String,
 }
 
-fn process(input: &Path) -> Result<(), MyError> {
-    let file = File::open(input)?;
+/// Skipped malformed rows, reported to an optional csv writer.
+struct Skipper<W: Write> {
+    mode: ErrorMode,
+    skipped: u64,
+    wtr: Option<csv::Writer<W>>,
+}
+impl<W: Write> Skipper<W> {
+    /// Skip a malformed row, or fail with its `error` when the [ErrorMode] does not allow it.
+    fn skip(&mut self, error: ParseError) -> Result<(), MyError> {
+        let max = match self.mode {
+            ErrorMode::Fail => return Err(Box::new(error).into()),
+            ErrorMode::Skip { max } => max,
+        };
+        self.skipped += 1;
+        // the row crossing the maximum is reported before failing
+        if let Some(wtr) = self.wtr.as_mut() {
+            let ParseError { record, line, byte, field, value, message } = error;
+            wtr.serialize(Skipped { record, line, byte, field, value, error: message })?;
+        }
+        if matches!(max, Some(max) if self.skipped > max) {
+            if let Some(wtr) = self.wtr.as_mut() {
+                wtr.flush()?;
+            }
+            return Err(MyError::TooManyErrors { errors: self.skipped })
+        }
+        Ok(())
+    }
+}
+
+/// Row of the client withdrawal limits, no limit when a field is empty.
+#[derive(Debug, Deserialize)]
+struct LimitsRow {
+    client: ClientId,
+    overdraft: Option<FourDecimals>,
+    max_withdrawal: Option<FourDecimals>,
+    max_daily_withdrawal: Option<FourDecimals>,
+}
+impl LimitsRow {
+    fn into_limits(self) -> Result<(ClientId, Limits), MyError> {
+        let LimitsRow { client, overdraft, max_withdrawal, max_daily_withdrawal } = self;
+        let negative = [overdraft, max_withdrawal, max_daily_withdrawal].into_iter()
+            .flatten()
+            .any(FourDecimals::is_negative);
+        if negative {
+            return Err(MyError::InvalidLimits { client })
+        }
+        Ok((client, Limits {
+            overdraft: overdraft.unwrap_or_default(),
+            max_withdrawal,
+            max_daily_withdrawal,
+        }))
+    }
+}
+
+/// Read the withdrawal [Limits] of clients.
+fn read_limits(path: &PathBuf) -> Result<Vec<(ClientId, Limits)>, MyError> {
     let mut rdr = csv::ReaderBuilder::new()
         .trim(csv::Trim::All)
-        .flexible(true)
         .has_headers(true)
-        .from_reader(file);
+        .from_path(path)?;
+    rdr.deserialize()
+        .map(|row: Result<LimitsRow, csv::Error>| row?.into_limits())
+        .collect()
+}
+
+/// Describe a csv `error` reading or deserializing a [Transaction] `record`,
+/// failing with io errors, which are not row errors.
+fn parse_error(error: csv::Error, record: Option<&csv::StringRecord>, headers: &csv::StringRecord)
+    -> Result<ParseError, MyError>
+{
+    // nothing after an io error can be read
+    if error.is_io_error() {
+        return Err(error.into())
+    }
+    let mut error = ParseError::new(&error, record, headers);
+    // custom deserialization errors of amounts and types do not carry their field
+    if let (None, Some(record)) = (&error.field, record) {
+        if let Some(index) = invalid_field(record, headers) {
+            error.field = headers.get(index).map(str::to_string);
+            error.value = record.get(index).map(str::to_string);
+        }
+    }
+    Ok(error)
+}
 
-    let mut clients = Clients::new();
-    for result in rdr.deserialize() {
-        let transaction: Transaction = result?;
-        clients.apply(transaction);
+/// Index of the first field of a [Transaction] `record` that does not deserialize.
+fn invalid_field(record: &csv::StringRecord, headers: &csv::StringRecord) -> Option<usize> {
+    fn valid<T: serde::de::DeserializeOwned>(value: &str) -> bool {
+        csv::StringRecord::from(vec![value]).deserialize::<T>(None).is_ok()
     }
+    headers.iter().zip(record.iter()).position(|(name, value)| !match name {
+        "type" => valid::<TransactionType>(value),
+        "client" => valid::<ClientId>(value),
+        "tx" => valid::<TxId>(value),
+        "amount" => valid::<Option<FourDecimals>>(value),
+        _ => true,
+    })
+}
+
+/// [Transaction] read from an input row.
+#[derive(Debug)]
+struct Row {
+    line: u64,
+    /// Whether the optional `date` column changed since the previous row,
+    /// starting a new day of the daily withdrawal limits.
+    new_day: bool,
+    transaction: Transaction,
+}
+
+/// Record of a [Transaction] that was not applied.
+#[derive(Debug, Serialize)]
+struct Reject {
+    line: u64,
+    r#type: TransactionType,
+    client: ClientId,
+    tx: TxId,
+    amount: Option<FourDecimals>,
+    reason: Rejection,
+}
+impl Reject {
+    fn new(line: u64, transaction: Transaction, reason: Rejection) -> Self {
+        let Transaction { r#type, client, tx, amount } = transaction;
+        Self { line, r#type, client, tx, amount, reason }
+    }
+}
 
-    let mut wtr = csv::WriterBuilder::new()
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("Error [{}] : {}", error.code(), error);
+            ExitCode::from(error.category().exit_code())
+        },
+    }
+}
+
+fn run() -> Result<(), MyError> {
+    let args = Args::parse(env::args().skip(1))?;
+
+    let input: Box<dyn Read> = match &args.input {
+        None => Box::new(io::stdin().lock()),
+        Some(path) => Box::new(File::open(path)?),
+    };
+    let output: Box<dyn Write> = match &args.output {
+        None => Box::new(io::stdout().lock()),
+        Some(path) => Box::new(File::create(path)?),
+    };
+
+    process(input, output, &args)
+}
+
+fn process(input: impl Read, output: impl Write, args: &Args) -> Result<(), MyError> {
+    let limits = match &args.limits {
+        None => Vec::new(),
+        Some(path) => read_limits(path)?,
+    };
+
+    let mut rdr = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .flexible(true)
+        .has_headers(true)
+        .from_reader(input);
+    let headers = rdr.headers()?.clone();
+
+    let mut rejects = match &args.rejects {
+        None => None,
+        Some(path) => Some(csv::WriterBuilder::new()
+            .has_headers(true)
+            .from_path(path)?),
+    };
+    let mut reject = |reject: Reject| -> Result<(), MyError> {
+        if let Some(wtr) = rejects.as_mut() {
+            wtr.serialize(reject)?;
+        }
+        Ok(())
+    };
+
+    let mut skipper = Skipper {
+        mode: args.on_error,
+        skipped: 0,
+        wtr: match &args.errors {
+            None => None,
+            Some(path) => Some(csv::WriterBuilder::new()
+                .has_headers(true)
+                .from_path(path)?),
+        },
+    };
+
+    let date = headers.iter().position(|name| name == "date");
+    let mut day: Option<String> = None;
+    let transactions = rdr.records().filter_map(|result| {
+        let parsed = result.map_err(|error| parse_error(error, None, &headers)).and_then(|record| {
+            let line = record.position().map_or(0, |position| position.line());
+            let transaction = match record.deserialize(Some(&headers)) {
+                Ok(transaction) => transaction,
+                Err(error) => return Err(parse_error(error, Some(&record), &headers)),
+            };
+            let new_day = match date.and_then(|index| record.get(index)).filter(|date| !date.is_empty()) {
+                Some(date) if day.as_deref() != Some(date) => day.replace(date.to_string()).is_some(),
+                _ => false,
+            };
+            Ok(Row { line, new_day, transaction })
+        });
+        match parsed {
+            Ok(row) => Some(Ok(row)),
+            Err(Ok(error)) => skipper.skip(error).err().map(Err),
+            Err(Err(error)) => Some(Err(error)),
+        }
+    });
+
+    let wtr = csv::WriterBuilder::new()
         .has_headers(true)
-        .from_writer(stdout());
+        .from_writer(output);
+    match &args.store {
+        None => {
+            let clients = apply(transactions, &mut reject, args, &limits, MemoryStorage, MemoryStorage)?;
+            write_clients(wtr, clients)?;
+        },
+        Some(dir) => {
+            let tx_amounts = DiskStorage::create(dir.join("tx_amounts.store"))?;
+            let disputes = DiskStorage::create(dir.join("disputes.store"))?;
+            let clients = apply(transactions, &mut reject, args, &limits, tx_amounts, disputes)?;
+            write_clients(wtr, clients)?;
+        },
+    }
+    if let Some(mut wtr) = rejects {
+        wtr.flush()?;
+    }
+    if let Some(mut wtr) = skipper.wtr {
+        wtr.flush()?;
+    }
+
+    Ok(())
+}
+
+/// Apply all `transactions` with withdrawal `limits`, starting a new day of the daily
+/// limits when the date changes, reporting rejected ones, and sort the resulting [Client]s.
+fn apply<St>(
+    transactions: impl Iterator<Item = Result<Row, MyError>>,
+    mut reject: impl FnMut(Reject) -> Result<(), MyError>,
+    args: &Args,
+    limits: &[(ClientId, Limits)],
+    tx_amounts: St,
+    disputes: St,
+) -> Result<Vec<Client<4, St::Store>>, MyError>
+where
+    St: Storage<4> + Clone + Send + 'static,
+    St::Store: Send,
+    St::Error: Send,
+    MyError: From<St::Error>,
+{
+    let clients = if args.threads > 1 {
+        let mut clients = ShardedClients::with_storage(
+            args.threads, args.policy, tx_amounts, disputes);
+        for (client, limits) in limits {
+            clients.set_limits(*client, *limits);
+        }
+        for result in transactions {
+            let Row { line, new_day, transaction } = result?;
+            if new_day {
+                clients.start_day();
+            }
+            clients.apply((line, transaction), transaction)?;
+            for ((line, transaction), reason) in clients.rejections() {
+                reject(Reject::new(line, transaction, reason))?;
+            }
+        }
+        let (clients, rejections) = clients.finish()?;
+        for ((line, transaction), reason) in rejections {
+            reject(Reject::new(line, transaction, reason))?;
+        }
+        clients
+    }
+    else {
+        let mut clients = Clients::with_storage(args.policy, tx_amounts, disputes);
+        for (client, limits) in limits {
+            clients.set_limits(*client, *limits);
+        }
+        for result in transactions {
+            let Row { line, new_day, transaction } = result?;
+            if new_day {
+                clients.start_day();
+            }
+            if let Err(reason) = clients.try_apply(transaction)? {
+                reject(Reject::new(line, transaction, reason))?;
+            }
+        }
+        clients
+    };
+
+    Ok(clients.into_sorted(args.sort))
+}
+
+fn write_clients<S: TxStore<4>>(
+    mut wtr: csv::Writer<impl Write>,
+    clients: Vec<Client<4, S>>,
+) -> Result<(), MyError> {
     for client in clients {
         wtr.serialize(client)?;
     }
     wtr.flush()?;
-
     Ok(())
 }
@@ -0,0 +1,118 @@
+//! Outcome of applying a [Transaction](crate::Transaction).
+
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::TransactionError;
+
+/// [Transaction](crate::Transaction) was applied to the client.
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Applied {
+    /// Funds were deposited.
+    Deposited,
+    /// Funds were withdrawn.
+    Withdrawn,
+    /// Disputed funds were held.
+    Disputed,
+    /// Held funds were released.
+    Resolved,
+    /// Held funds were charged back and the client was locked.
+    ChargedBack,
+    /// Exact replay of an applied transaction, nothing changed.
+    Replayed,
+    /// Ignored by the [Policy](crate::Policy), nothing changed.
+    Ignored,
+}
+
+/// [Transaction](crate::Transaction) was not applied to the client.
+#[allow(missing_docs)]
+#[derive(Error, Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Rejection {
+    #[error("Client account is locked.")]
+    Locked,
+    #[error("Client account is frozen.")]
+    Frozen,
+    #[error("Client account is closed.")]
+    Closed,
+    #[error("Transaction amount is negative.")]
+    NegativeAmount,
+    #[error("Transaction amount is zero.")]
+    ZeroAmount,
+    #[error("Deposit or withdrawal without amount.")]
+    MissingAmount,
+    #[error("Dispute, resolve or chargeback with an amount.")]
+    UnexpectedAmount,
+    #[error("Insufficient available funds.")]
+    InsufficientFunds,
+    #[error("Overdraft limit exceeded.")]
+    OverdraftExceeded,
+    #[error("Single withdrawal limit exceeded.")]
+    WithdrawalLimitExceeded,
+    #[error("Daily withdrawal limit exceeded.")]
+    DailyLimitExceeded,
+    #[error("Transaction is unknown to the client.")]
+    UnknownTransaction,
+    /// Also for ids never used by the client below the retention window,
+    /// see [Retention](crate::Retention).
+    #[error("Transaction is older than the retention window.")]
+    Expired,
+    #[error("Transaction id is already used.")]
+    DuplicateTransaction,
+    #[error("Transaction belongs to another client.")]
+    ClientMismatch,
+    #[error("Transaction is already disputed.")]
+    AlreadyDisputed,
+    #[error("Transaction is not disputed.")]
+    NotDisputed,
+    #[error("Transaction dispute is already resolved.")]
+    AlreadyResolved,
+    #[error("Transaction is already charged back.")]
+    AlreadyChargedBack,
+    #[error("Withdrawals cannot be disputed.")]
+    WithdrawalDispute,
+    #[error("Arithmetic overflow.")]
+    Overflow,
+    #[error("Transaction id cannot be kept by the storage.")]
+    TxIdOutOfRange,
+}
+impl From<TransactionError> for Rejection {
+    fn from(error: TransactionError) -> Self {
+        match error {
+            TransactionError::MissingAmount => Rejection::MissingAmount,
+            TransactionError::UnexpectedAmount => Rejection::UnexpectedAmount,
+            TransactionError::ZeroAmount => Rejection::ZeroAmount,
+            TransactionError::NegativeAmount => Rejection::NegativeAmount,
+        }
+    }
+}
+impl Rejection {
+    /// Stable machine readable code, as serialized.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Rejection::Locked => "locked",
+            Rejection::Frozen => "frozen",
+            Rejection::Closed => "closed",
+            Rejection::NegativeAmount => "negative_amount",
+            Rejection::ZeroAmount => "zero_amount",
+            Rejection::MissingAmount => "missing_amount",
+            Rejection::UnexpectedAmount => "unexpected_amount",
+            Rejection::InsufficientFunds => "insufficient_funds",
+            Rejection::OverdraftExceeded => "overdraft_exceeded",
+            Rejection::WithdrawalLimitExceeded => "withdrawal_limit_exceeded",
+            Rejection::DailyLimitExceeded => "daily_limit_exceeded",
+            Rejection::UnknownTransaction => "unknown_transaction",
+            Rejection::Expired => "expired",
+            Rejection::DuplicateTransaction => "duplicate_transaction",
+            Rejection::ClientMismatch => "client_mismatch",
+            Rejection::AlreadyDisputed => "already_disputed",
+            Rejection::NotDisputed => "not_disputed",
+            Rejection::AlreadyResolved => "already_resolved",
+            Rejection::AlreadyChargedBack => "already_charged_back",
+            Rejection::WithdrawalDispute => "withdrawal_dispute",
+            Rejection::Overflow => "overflow",
+            Rejection::TxIdOutOfRange => "tx_id_out_of_range",
+        }
+    }
+}
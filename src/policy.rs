@@ -0,0 +1,82 @@
+//! Processing policy of [Clients](crate::Clients).
+
+use crate::{FixedDecimals, TxIdRepr};
+
+/// Retention of past transactions available for disputes.
+///
+/// Transactions outside of the retention window are forgotten and disputing them
+/// is rejected with [Rejection::Expired](crate::Rejection::Expired).
+/// Forgotten transactions leave no trace, so disputing an id the client never used is
+/// also rejected as expired when the id is below the window: more than `max_age` ids below
+/// the newest transaction, or below the highest id evicted for `max_entries`.
+/// Transactions under dispute are kept until resolved or charged back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Retention {
+    /// Keep transactions with id at most `max_age` below the newest transaction id
+    /// of the client.
+    pub max_age: Option<TxIdRepr>,
+    /// Keep at most `max_entries` transactions per client, forgetting the earliest received.
+    pub max_entries: Option<usize>,
+}
+
+/// Disputes of a transaction whose previous dispute was settled.
+///
+/// A charged back transaction is never disputed again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Redispute {
+    /// Rejected with [Rejection::AlreadyResolved](crate::Rejection::AlreadyResolved).
+    Never,
+    /// A resolved transaction can be disputed again.
+    Resolved,
+}
+impl Default for Redispute {
+    fn default() -> Self {
+        Redispute::Never
+    }
+}
+
+/// Disputes of withdrawals.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WithdrawalDisputes {
+    /// Like a deposit, the amount is moved from available to held,
+    /// a chargeback removes it.
+    Hold,
+    /// Applied as a no-op, [Applied::Ignored](crate::Applied::Ignored).
+    Ignore,
+    /// Rejected with [Rejection::WithdrawalDispute](crate::Rejection::WithdrawalDispute).
+    Reject,
+    /// The withdrawn amount is provisionally credited as held,
+    /// a resolve removes it and a chargeback makes it available.
+    ReverseCredit,
+}
+impl Default for WithdrawalDisputes {
+    fn default() -> Self {
+        WithdrawalDisputes::Hold
+    }
+}
+
+/// Processing policy of [Clients](crate::Clients).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Policy {
+    /// [Retention] of past transactions, unbounded by default.
+    pub retention: Retention,
+    /// Apply an exact replay (same type, client and amount) of a deposit or withdrawal
+    /// as a no-op, instead of rejecting it as a duplicate transaction id.
+    pub idempotent_replays: bool,
+    /// [Redispute] of settled transactions, never by default.
+    pub redispute: Redispute,
+    /// [WithdrawalDisputes], held like deposits by default.
+    pub withdrawal_disputes: WithdrawalDisputes,
+}
+
+/// Withdrawal limits of a client with `SCALE` decimals amounts, unlimited when `None`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Limits<const SCALE: u32 = 4> {
+    /// Amount the available balance can go below zero by withdrawals.
+    pub overdraft: FixedDecimals<SCALE>,
+    /// Maximal amount of a single withdrawal.
+    pub max_withdrawal: Option<FixedDecimals<SCALE>>,
+    /// Maximal amount withdrawn during a day, less the withdrawals reversed by a chargeback,
+    /// days are started by [Clients::start_day](crate::Clients::start_day).
+    pub max_daily_withdrawal: Option<FixedDecimals<SCALE>>,
+}
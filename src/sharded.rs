@@ -0,0 +1,338 @@
+//! Parallel processing of [Transaction]s sharded by client id.
+
+use std::fmt;
+use std::panic;
+use std::sync::mpsc::{channel, sync_channel, Receiver, Sender, SyncSender};
+use std::thread::{self, JoinHandle};
+
+use crate::clients::TxOwners;
+use crate::store::{Storage, MemoryStorage};
+use crate::{Clients, Transaction, Rejection, Policy, Retention, Limits, ClientId, TxId};
+
+/// Transactions sent to a shard at once.
+const BATCH_SIZE: usize = 1024;
+/// Batches queued per shard before the reader blocks.
+const QUEUE_SIZE: usize = 16;
+
+/// Work of a shard, applied in order.
+enum Work<T, const SCALE: u32> {
+    /// Tagged transaction, with whether its id was already used by the client.
+    Apply(T, Transaction<SCALE>, bool),
+    /// Withdrawal limits of a client.
+    Limits(ClientId, Limits<SCALE>),
+    /// Start of a new day of daily withdrawal limits.
+    StartDay,
+}
+type Batch<T, const SCALE: u32> = Vec<Work<T, SCALE>>;
+type Rejections<T> = Vec<(T, Rejection)>;
+type Processed<const SCALE: u32, St> = Result<Clients<SCALE, St>, <St as Storage<SCALE>>::Error>;
+
+/// Reply of a worker.
+enum Reply<T> {
+    /// Rejections of an applied batch, with the ids of the transactions it forgot.
+    Applied(Rejections<T>, Vec<TxId>),
+    /// The worker stopped, after its last batch, failing or panicking.
+    Stopped,
+}
+
+/// Sends [Reply::Stopped] when the worker stops, however it stops.
+struct Stopping<T>(Sender<Reply<T>>);
+impl<T> Drop for Stopping<T> {
+    fn drop(&mut self) {
+        let _ = self.0.send(Reply::Stopped);
+    }
+}
+
+struct Shard<T, const SCALE: u32, St: Storage<SCALE>> {
+    batch: Batch<T, SCALE>,
+    /// Number of batches sent.
+    sent: usize,
+    sender: SyncSender<Batch<T, SCALE>>,
+    worker: JoinHandle<Processed<SCALE, St>>,
+}
+impl<T, const SCALE: u32, St> Shard<T, SCALE, St>
+where
+    T: Send + 'static,
+    St: Storage<SCALE> + Send + 'static,
+    St::Store: Send,
+    St::Error: Send,
+{
+    /// Spawn a worker replying the rejections and forgotten transaction ids
+    /// of every batch to `applied`.
+    fn spawn(clients: Clients<SCALE, St>, applied: Sender<Reply<T>>) -> Self {
+        let (sender, receiver) = sync_channel::<Batch<T, SCALE>>(QUEUE_SIZE);
+        let worker = thread::spawn(move || {
+            let applied = Stopping(applied);
+            let mut clients = clients;
+            for batch in receiver {
+                let mut rejections = Vec::new();
+                let mut forgotten = Vec::new();
+                for work in batch {
+                    match work {
+                        Work::Apply(tag, transaction, known) => {
+                            if let Err(reason) = clients.try_apply_known(transaction, known, &mut forgotten)? {
+                                rejections.push((tag, reason));
+                            }
+                        },
+                        Work::Limits(client, limits) => clients.set_limits(client, limits),
+                        Work::StartDay => clients.start_day(),
+                    }
+                }
+                // every batch is acknowledged, a failed send means the dispatcher stopped
+                if applied.0.send(Reply::Applied(rejections, forgotten)).is_err() {
+                    break
+                }
+            }
+            Ok(clients)
+        });
+
+        Self { batch: Vec::with_capacity(BATCH_SIZE), sent: 0, sender, worker }
+    }
+    fn push(&mut self, work: Work<T, SCALE>) {
+        self.batch.push(work);
+        if self.batch.len() >= BATCH_SIZE {
+            self.flush();
+        }
+    }
+    fn flush(&mut self) {
+        if self.batch.is_empty() {
+            return
+        }
+        let batch = std::mem::replace(&mut self.batch, Vec::with_capacity(BATCH_SIZE));
+        // a failed send means the worker stopped, its error or panic is propagated on join
+        if self.sender.send(batch).is_ok() {
+            self.sent += 1;
+        }
+    }
+    fn join(mut self) -> Processed<SCALE, St> {
+        self.flush();
+        drop(self.sender);
+        self.worker.join().unwrap_or_else(|panic| panic::resume_unwind(panic))
+    }
+}
+
+/// [Clients] processed in parallel by worker threads.
+///
+/// Transactions are dispatched to `shards` workers by `client % shards`,
+/// so the transactions of a client are applied in order by a single worker.
+/// Every transaction carries a tag `T`, returned with its [Rejection]
+/// as soon as the batch holding it is applied, see [ShardedClients::rejections].
+/// Transaction ids are checked for uniqueness and ownership across clients before dispatching,
+/// the ids of transactions forgotten by the workers are released when their batch returns.
+/// With a [Retention], a transaction whose id is in use waits for the dispatched transactions
+/// to be applied, so that it is checked after the ids they forget are released,
+/// like with sequential [Clients].
+pub struct ShardedClients<T, const SCALE: u32 = 4, St: Storage<SCALE> = MemoryStorage> {
+    policy: Policy,
+    tx_amounts: St,
+    disputes: St,
+    owners: TxOwners,
+    /// Rejections not yet taken.
+    rejections: Rejections<T>,
+    applied: Receiver<Reply<T>>,
+    /// Number of batches applied.
+    acknowledged: usize,
+    /// Whether a worker stopped, its batches are no longer acknowledged.
+    stopped: bool,
+    shards: Vec<Shard<T, SCALE, St>>,
+}
+impl<T: Send + 'static, const SCALE: u32> ShardedClients<T, SCALE> {
+    /// Spawn `shards` workers, at least one.
+    pub fn new(shards: usize) -> Self {
+        Self::with_policy(shards, Policy::default())
+    }
+    /// Spawn `shards` workers, at least one, applying transactions with a [Policy].
+    pub fn with_policy(shards: usize, policy: Policy) -> Self {
+        Self::with_storage(shards, policy, MemoryStorage, MemoryStorage)
+    }
+}
+impl<T, const SCALE: u32, St> ShardedClients<T, SCALE, St>
+where
+    T: Send + 'static,
+    St: Storage<SCALE> + Clone + Send + 'static,
+    St::Store: Send,
+    St::Error: Send,
+{
+    /// Spawn `shards` workers, at least one, applying transactions with a [Policy]
+    /// and keeping transaction amounts and disputes in separate [Storage]s.
+    pub fn with_storage(shards: usize, policy: Policy, tx_amounts: St, disputes: St) -> Self {
+        let (sender, applied) = channel();
+        let shards = (0..shards.max(1))
+            .map(|_| Shard::spawn(
+                Clients::with_storage(policy, tx_amounts.clone(), disputes.clone()), sender.clone()))
+            .collect();
+        Self {
+            policy, tx_amounts, disputes,
+            owners: TxOwners::default(),
+            rejections: Vec::new(),
+            applied,
+            acknowledged: 0,
+            stopped: false,
+            shards,
+        }
+    }
+    /// Dispatch a tagged [Transaction] to the shard of its client.
+    ///
+    /// Fails when the [Storage] fails to check the owner of the transaction id.
+    pub fn apply(&mut self, tag: T, transaction: Transaction<SCALE>) -> Result<(), St::Error> {
+        self.receive();
+        // ids forgotten by dispatched transactions may not be released yet
+        if self.policy.retention != Retention::default()
+            && self.owners.contested(&self.tx_amounts, &transaction)?
+        {
+            self.sync();
+        }
+        let known = match self.owners.claim(&self.tx_amounts, &transaction)? {
+            Ok(known) => known,
+            Err(reason) => {
+                self.rejections.push((tag, reason));
+                return Ok(())
+            },
+        };
+        let index = transaction.client.0 as usize % self.shards.len();
+        self.shards[index].push(Work::Apply(tag, transaction, known));
+        Ok(())
+    }
+    /// Collect the rejections and release the forgotten transaction ids of applied batches.
+    fn receive(&mut self) {
+        while let Ok(reply) = self.applied.try_recv() {
+            self.reply(reply);
+        }
+    }
+    /// Wait until all dispatched transactions are applied, or a worker stopped.
+    fn sync(&mut self) {
+        self.shards.iter_mut().for_each(Shard::flush);
+        let sent: usize = self.shards.iter().map(|shard| shard.sent).sum();
+        while self.acknowledged < sent && !self.stopped {
+            match self.applied.recv() {
+                Ok(reply) => self.reply(reply),
+                Err(_) => break,
+            }
+        }
+    }
+    fn reply(&mut self, reply: Reply<T>) {
+        match reply {
+            Reply::Applied(rejections, forgotten) => {
+                self.rejections.extend(rejections);
+                self.owners.release(forgotten);
+                self.acknowledged += 1;
+            },
+            Reply::Stopped => self.stopped = true,
+        }
+    }
+    /// Set the withdrawal [Limits] of a client, from its next dispatched transaction.
+    pub fn set_limits(&mut self, client: ClientId, limits: Limits<SCALE>) {
+        let index = client.0 as usize % self.shards.len();
+        self.shards[index].push(Work::Limits(client, limits));
+    }
+    /// Start a new day of the daily withdrawal limits of all clients,
+    /// from their next dispatched transaction.
+    pub fn start_day(&mut self) {
+        self.shards.iter_mut().for_each(|shard| shard.push(Work::StartDay));
+    }
+    /// Take the rejections received so far, without waiting for the workers.
+    ///
+    /// Rejections are in order within a client, not across clients.
+    pub fn rejections(&mut self) -> impl Iterator<Item = (T, Rejection)> {
+        self.receive();
+        std::mem::take(&mut self.rejections).into_iter()
+    }
+    /// Wait for all workers and merge their [Clients], with the rejections
+    /// not taken by [ShardedClients::rejections].
+    ///
+    /// Fails with the first [Storage] error of a worker.
+    pub fn finish(self) -> Result<(Clients<SCALE, St>, Rejections<T>), St::Error> {
+        let mut clients = Clients::with_storage(self.policy, self.tx_amounts, self.disputes);
+        clients.owners = self.owners;
+        let mut error = None;
+        for shard in self.shards {
+            match shard.join() {
+                Ok(shard_clients) => clients.merge(shard_clients),
+                Err(shard_error) => error = error.or(Some(shard_error)),
+            }
+        }
+        let mut rejections = self.rejections;
+        for reply in self.applied.try_iter() {
+            if let Reply::Applied(shard_rejections, forgotten) = reply {
+                rejections.extend(shard_rejections);
+                clients.owners.release(forgotten);
+            }
+        }
+        match error {
+            Some(error) => Err(error),
+            None => Ok((clients, rejections)),
+        }
+    }
+}
+impl<T, const SCALE: u32, St: Storage<SCALE>> fmt::Debug for ShardedClients<T, SCALE, St> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ShardedClients")
+            .field("shards", &self.shards.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    use crate::{FourDecimals, TransactionType, ClientIdRepr, TxId, TxIdRepr};
+
+    fn transactions() -> Vec<Transaction> {
+        let mut transactions = Vec::new();
+        for i in 0..10000u64 {
+            let tx = i as TxIdRepr;
+            let client = ClientId((tx * 7 % 13) as ClientIdRepr);
+            let r#type = match tx % 5 {
+                0 | 1 => TransactionType::Deposit,
+                2 => TransactionType::Withdrawal,
+                3 => TransactionType::Dispute,
+                _ => TransactionType::Resolve,
+            };
+            let amount = match r#type {
+                TransactionType::Deposit | TransactionType::Withdrawal =>
+                    Some(FourDecimals::new(i % 17, i % 10000)),
+                _ => None,
+            };
+            let tx = match r#type {
+                TransactionType::Dispute | TransactionType::Resolve => tx - 3,
+                // duplicate ids, across clients and within a client
+                _ if tx % 11 == 0 => tx / 2,
+                _ => tx,
+            };
+            transactions.push(Transaction { r#type, client, tx: TxId(tx), amount });
+        }
+        transactions
+    }
+
+    #[test]
+    pub fn same_as_sequential() {
+        let retention = |max_age, max_entries| Policy {
+            retention: Retention { max_age, max_entries }, ..Policy::default()
+        };
+        // forgotten ids are reused by later transactions with a retention
+        for policy in [Policy::default(), retention(None, Some(3)), retention(Some(40), Some(5))] {
+            let mut sequential = Clients::with_policy(policy);
+            let mut sequential_rejections = Vec::new();
+            for (i, transaction) in transactions().into_iter().enumerate() {
+                if let Err(reason) = sequential.apply(transaction) {
+                    sequential_rejections.push((i, reason));
+                }
+            }
+
+            let mut sharded = ShardedClients::with_policy(4, policy);
+            let mut rejections = Vec::new();
+            for (i, transaction) in transactions().into_iter().enumerate() {
+                sharded.apply(i, transaction).unwrap();
+                rejections.extend(sharded.rejections());
+            }
+            let (clients, remaining) = sharded.finish().unwrap();
+            rejections.extend(remaining);
+            rejections.sort_by_key(|(i, _)| *i);
+
+            assert_eq!(clients.sorted(), sequential.sorted());
+            assert_eq!(rejections, sequential_rejections);
+            assert!(!rejections.is_empty());
+        }
+    }
+}
@@ -0,0 +1,512 @@
+//! Storage of transactions by transaction id.
+
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::{FixedDecimals, TransactionType, ClientId, ClientIdRepr, TxId};
+
+/// Dispute lifecycle state of a transaction.
+///
+/// `Processed -> Disputed -> Resolved | ChargedBack`, a resolved transaction
+/// can be disputed again depending on the [Redispute](crate::Redispute) policy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TxState {
+    /// Applied, never disputed.
+    Processed,
+    /// Under dispute.
+    Disputed,
+    /// Dispute was resolved.
+    Resolved,
+    /// Dispute was charged back.
+    ChargedBack,
+}
+
+/// Stored transaction with `SCALE` decimals amount.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TxRecord<const SCALE: u32 = 4> {
+    /// [TransactionType].
+    pub r#type: TransactionType,
+    /// Amount.
+    pub amount: FixedDecimals<SCALE>,
+    /// [TxState].
+    pub state: TxState,
+}
+
+/// Storage of the past transactions of a [Client](crate::Client) by transaction id.
+pub trait TxStore<const SCALE: u32>: fmt::Debug {
+    /// Error of a storage operation.
+    type Error;
+
+    /// Transaction `tx`.
+    fn get(&self, tx: TxId) -> Result<Option<TxRecord<SCALE>>, Self::Error>;
+    /// Store transaction `tx`, replacing a previous one.
+    fn insert(&mut self, tx: TxId, record: TxRecord<SCALE>) -> Result<(), Self::Error>;
+    /// Remove transaction `tx`, returning it.
+    fn remove(&mut self, tx: TxId) -> Result<Option<TxRecord<SCALE>>, Self::Error>;
+    /// Number of stored transactions.
+    fn len(&self) -> usize;
+    /// Whether no transactions are stored.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// Forget all transactions with id below `tx`, returning their ids.
+    fn forget_below(&mut self, tx: TxId) -> Result<Vec<TxId>, Self::Error>;
+}
+
+/// Opens the [TxStore] of every client.
+pub trait Storage<const SCALE: u32>: fmt::Debug {
+    /// Error of a storage operation.
+    type Error;
+    /// [TxStore] of a client.
+    type Store: TxStore<SCALE, Error = Self::Error>;
+
+    /// Whether the storage keeps the owner client of transaction ids,
+    /// otherwise they are kept in memory by [Clients](crate::Clients).
+    const OWNERS: bool = false;
+
+    /// Open an empty [TxStore] for `client`.
+    fn open(&self, client: ClientId) -> Result<Self::Store, Self::Error>;
+    /// Client using transaction id `tx`, `None` when unused or without [Storage::OWNERS].
+    fn owner(&self, _tx: TxId) -> Result<Option<ClientId>, Self::Error> {
+        Ok(None)
+    }
+    /// Use transaction id `tx` for `client` before storing its transaction,
+    /// nothing without [Storage::OWNERS].
+    ///
+    /// Whether the id was claimed, `false` when the storage cannot keep transaction `tx`.
+    fn claim(&self, _tx: TxId, _client: ClientId) -> Result<bool, Self::Error> {
+        Ok(true)
+    }
+}
+
+/// In memory [TxStore], ordered by transaction id.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct MemoryStore<const SCALE: u32> {
+    records: BTreeMap<TxId, TxRecord<SCALE>>,
+}
+impl<const SCALE: u32> TxStore<SCALE> for MemoryStore<SCALE> {
+    type Error = Infallible;
+
+    fn get(&self, tx: TxId) -> Result<Option<TxRecord<SCALE>>, Infallible> {
+        Ok(self.records.get(&tx).copied())
+    }
+    fn insert(&mut self, tx: TxId, record: TxRecord<SCALE>) -> Result<(), Infallible> {
+        self.records.insert(tx, record);
+        Ok(())
+    }
+    fn remove(&mut self, tx: TxId) -> Result<Option<TxRecord<SCALE>>, Infallible> {
+        Ok(self.records.remove(&tx))
+    }
+    fn len(&self) -> usize {
+        self.records.len()
+    }
+    fn forget_below(&mut self, tx: TxId) -> Result<Vec<TxId>, Infallible> {
+        if !matches!(self.records.keys().next(), Some(first) if *first < tx) {
+            return Ok(Vec::new())
+        }
+        let retained = self.records.split_off(&tx);
+        let forgotten = std::mem::replace(&mut self.records, retained);
+        Ok(forgotten.into_keys().collect())
+    }
+}
+
+/// [Storage] of [MemoryStore]s.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MemoryStorage;
+impl<const SCALE: u32> Storage<SCALE> for MemoryStorage {
+    type Error = Infallible;
+    type Store = MemoryStore<SCALE>;
+
+    fn open(&self, _client: ClientId) -> Result<MemoryStore<SCALE>, Infallible> {
+        Ok(MemoryStore::default())
+    }
+}
+
+/// Size of a client id in a transaction slot.
+const CLIENT_SIZE: usize = std::mem::size_of::<ClientIdRepr>();
+/// Size of a transaction slot: type (0 when empty), state, client id and amount minor units.
+const SLOT_SIZE: usize = 1 + 1 + CLIENT_SIZE + 16;
+/// Slot type of a transaction id used by a client, without a stored transaction.
+const CLAIMED: u8 = u8::MAX;
+/// Transaction types by slot type - 1.
+const TYPES: [TransactionType; 5] = [
+    TransactionType::Deposit,
+    TransactionType::Withdrawal,
+    TransactionType::Dispute,
+    TransactionType::Resolve,
+    TransactionType::Chargeback,
+];
+/// Error code of a write past the file size limit.
+#[cfg(unix)]
+const EFBIG: i32 = 27;
+/// Error code of a write past the file size limit.
+#[cfg(windows)]
+const EFBIG: i32 = 223;
+/// Transaction states by slot state.
+const STATES: [TxState; 4] = [
+    TxState::Processed,
+    TxState::Disputed,
+    TxState::Resolved,
+    TxState::ChargedBack,
+];
+
+/// Read from `file` at `offset`, without moving a shared cursor.
+#[cfg(unix)]
+fn read_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    std::os::unix::fs::FileExt::read_at(file, buf, offset)
+}
+/// Read from `file` at `offset`, without moving a shared cursor.
+#[cfg(windows)]
+fn read_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    std::os::windows::fs::FileExt::seek_read(file, buf, offset)
+}
+/// Write all of `buf` to `file` at `offset`, without moving a shared cursor.
+#[cfg(unix)]
+fn write_all_at(file: &File, buf: &[u8], offset: u64) -> io::Result<()> {
+    std::os::unix::fs::FileExt::write_all_at(file, buf, offset)
+}
+/// Write all of `buf` to `file` at `offset`, without moving a shared cursor.
+#[cfg(windows)]
+fn write_all_at(file: &File, mut buf: &[u8], mut offset: u64) -> io::Result<()> {
+    while !buf.is_empty() {
+        match std::os::windows::fs::FileExt::seek_write(file, buf, offset)? {
+            0 => return Err(io::ErrorKind::WriteZero.into()),
+            n => {
+                buf = &buf[n..];
+                offset += n as u64;
+            },
+        }
+    }
+    Ok(())
+}
+
+/// [Storage] of [DiskStore]s in a single file shared by all clients.
+///
+/// The file is addressed directly by transaction id, a transaction takes
+/// a fixed size slot at `tx * 22` bytes (with `u32` client ids), so the file is sparse and only the slots
+/// of stored transactions take disk space. Transaction ids are global, a slot
+/// taken by one client cannot be stored by another, the slot keeps the owner
+/// client of the id ([Storage::OWNERS]) so that it is not held in memory.
+///
+/// Slots are read and written at their position without locking, the clients of
+/// different workers use different slots. Transaction ids whose slot is past the
+/// largest file offset, or past the file size limit of the file system, are not claimed.
+/// Their deposits and withdrawals are rejected with [Rejection::TxIdOutOfRange](crate::Rejection::TxIdOutOfRange).
+#[derive(Debug, Clone)]
+pub struct DiskStorage {
+    file: Arc<File>,
+}
+impl DiskStorage {
+    /// Create the storage file at `path`, truncating an existing one.
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        Ok(Self { file: Arc::new(file) })
+    }
+}
+impl<const SCALE: u32> Storage<SCALE> for DiskStorage {
+    type Error = io::Error;
+    type Store = DiskStore<SCALE>;
+
+    const OWNERS: bool = true;
+
+    fn open(&self, client: ClientId) -> io::Result<DiskStore<SCALE>> {
+        Ok(DiskStore { file: self.file.clone(), client, len: 0 })
+    }
+    fn owner(&self, tx: TxId) -> io::Result<Option<ClientId>> {
+        let store: DiskStore<SCALE> = self.open(ClientId::default())?;
+        Ok(store.read_slot(tx)?.map(|(client, _)| client))
+    }
+    fn claim(&self, tx: TxId, client: ClientId) -> io::Result<bool> {
+        if DiskStore::<SCALE>::offset(tx).is_none() {
+            return Ok(false)
+        }
+        let store: DiskStore<SCALE> = self.open(client)?;
+        match store.write_slot(tx, Slot::Claimed) {
+            Ok(()) => Ok(true),
+            Err(error) if error.raw_os_error() == Some(EFBIG) => Ok(false),
+            Err(error) => Err(error),
+        }
+    }
+}
+
+/// Content of a [DiskStorage] slot.
+enum Slot<const SCALE: u32> {
+    Empty,
+    Claimed,
+    Stored(TxRecord<SCALE>),
+}
+
+/// [TxStore] of a client in a [DiskStorage] file.
+///
+/// Only the number of stored transactions is kept in memory.
+/// Retention by age is not supported, [TxStore::forget_below] fails.
+#[derive(Debug)]
+pub struct DiskStore<const SCALE: u32> {
+    file: Arc<File>,
+    client: ClientId,
+    len: usize,
+}
+impl<const SCALE: u32> DiskStore<SCALE> {
+    /// Offset of the slot of `tx`, `None` past the largest file offset.
+    // transaction ids are u64 with the tx-id-u64 feature
+    #[allow(clippy::useless_conversion)]
+    fn offset(tx: TxId) -> Option<u64> {
+        u64::from(tx.0).checked_mul(SLOT_SIZE as u64)
+            .filter(|offset| *offset <= i64::MAX as u64 - SLOT_SIZE as u64)
+    }
+    /// Owner client and transaction of a used slot.
+    fn read_slot(&self, tx: TxId) -> io::Result<Option<(ClientId, Option<TxRecord<SCALE>>)>> {
+        let mut slot = [0; SLOT_SIZE];
+        // slots out of range are never claimed
+        let offset = match Self::offset(tx) {
+            Some(offset) => offset,
+            None => return Ok(None),
+        };
+        // slots past the end of the file are empty
+        let mut read = 0;
+        while read < SLOT_SIZE {
+            match read_at(&self.file, &mut slot[read..], offset + read as u64) {
+                Ok(0) => break,
+                Ok(n) => read += n,
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => {},
+                Err(error) => return Err(error),
+            }
+        }
+
+        if slot[0] == 0 {
+            return Ok(None)
+        }
+        let mut client = [0; CLIENT_SIZE];
+        client.copy_from_slice(&slot[2..2 + CLIENT_SIZE]);
+        let client = ClientId(ClientIdRepr::from_le_bytes(client));
+        if slot[0] == CLAIMED {
+            return Ok(Some((client, None)))
+        }
+        let invalid = |what| io::Error::new(io::ErrorKind::InvalidData,
+            format!("disk store {} out of range", what));
+        let r#type = *TYPES.get(slot[0] as usize - 1).ok_or_else(|| invalid("type"))?;
+        let state = *STATES.get(slot[1] as usize).ok_or_else(|| invalid("state"))?;
+        let mut units = [0; 16];
+        units.copy_from_slice(&slot[2 + CLIENT_SIZE..]);
+        let amount = FixedDecimals::from_minor_units(i128::from_le_bytes(units))
+            .ok_or_else(|| invalid("amount"))?;
+        Ok(Some((client, Some(TxRecord { r#type, amount, state }))))
+    }
+    fn write_slot(&self, tx: TxId, content: Slot<SCALE>) -> io::Result<()> {
+        let mut slot = [0; SLOT_SIZE];
+        match content {
+            Slot::Empty => {},
+            Slot::Claimed => {
+                slot[0] = CLAIMED;
+                slot[2..2 + CLIENT_SIZE].copy_from_slice(&self.client.0.to_le_bytes());
+            },
+            Slot::Stored(record) => {
+                let r#type = TYPES.iter().position(|r#type| *r#type == record.r#type).unwrap_or(0);
+                let state = STATES.iter().position(|state| *state == record.state).unwrap_or(0);
+                slot[0] = r#type as u8 + 1;
+                slot[1] = state as u8;
+                slot[2..2 + CLIENT_SIZE].copy_from_slice(&self.client.0.to_le_bytes());
+                slot[2 + CLIENT_SIZE..].copy_from_slice(&record.amount.to_minor_units().to_le_bytes());
+            },
+        }
+        let offset = Self::offset(tx).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput,
+            format!("transaction {} is out of the disk store range", tx)))?;
+        write_all_at(&self.file, &slot, offset)
+    }
+    fn unsupported() -> io::Error {
+        io::Error::new(io::ErrorKind::Unsupported, "retention is not supported by the disk store")
+    }
+}
+impl<const SCALE: u32> TxStore<SCALE> for DiskStore<SCALE> {
+    type Error = io::Error;
+
+    fn get(&self, tx: TxId) -> io::Result<Option<TxRecord<SCALE>>> {
+        Ok(self.read_slot(tx)?
+            .filter(|(client, _)| *client == self.client)
+            .and_then(|(_, record)| record))
+    }
+    fn insert(&mut self, tx: TxId, record: TxRecord<SCALE>) -> io::Result<()> {
+        match self.read_slot(tx)? {
+            Some((client, _)) if client != self.client => {
+                return Err(io::Error::new(io::ErrorKind::AlreadyExists,
+                    format!("transaction {} is stored for client {}", tx, client)))
+            },
+            Some((_, Some(_))) => {},
+            Some((_, None)) | None => self.len += 1,
+        }
+        self.write_slot(tx, Slot::Stored(record))
+    }
+    fn remove(&mut self, tx: TxId) -> io::Result<Option<TxRecord<SCALE>>> {
+        let record = self.get(tx)?;
+        if record.is_some() {
+            self.write_slot(tx, Slot::Empty)?;
+            self.len -= 1;
+        }
+        Ok(record)
+    }
+    fn len(&self) -> usize {
+        self.len
+    }
+    fn forget_below(&mut self, _tx: TxId) -> io::Result<Vec<TxId>> {
+        Err(Self::unsupported())
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    use crate::{FourDecimals, TxIdRepr};
+
+    fn record(r#type: TransactionType, amount: FourDecimals) -> TxRecord {
+        TxRecord { r#type, amount, state: TxState::Processed }
+    }
+
+    fn store_roundtrip<S: TxStore<4>>(mut store: S) -> Result<(), S::Error> {
+        let deposit = record(TransactionType::Deposit, FourDecimals::new(12, 34));
+        let withdrawal = record(TransactionType::Withdrawal, -FourDecimals::new(12, 34));
+        assert_eq!(store.get(TxId(7))?, None);
+        store.insert(TxId(7), deposit)?;
+        store.insert(TxId(3), withdrawal)?;
+        let charged_back = TxRecord { state: TxState::ChargedBack, ..deposit };
+        store.insert(TxId(7), charged_back)?;
+        assert_eq!(store.len(), 2);
+        assert_eq!(store.get(TxId(7))?, Some(charged_back));
+        assert_eq!(store.get(TxId(3))?, Some(withdrawal));
+        assert_eq!(store.remove(TxId(3))?, Some(withdrawal));
+        assert_eq!(store.remove(TxId(3))?, None);
+        assert_eq!(store.get(TxId(3))?, None);
+        assert_eq!(store.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    pub fn memory() {
+        store_roundtrip(MemoryStore::default()).unwrap();
+
+        let mut store = MemoryStore::<4>::default();
+        for tx in [5, 1, 3] {
+            store.insert(TxId(tx), record(TransactionType::Deposit, FourDecimals::ONE)).unwrap();
+        }
+        assert_eq!(store.forget_below(TxId(3)), Ok(vec![TxId(1)]));
+        assert_eq!(store.len(), 2);
+        assert_eq!(store.get(TxId(1)), Ok(None));
+    }
+    #[test]
+    pub fn disk() {
+        let path = std::env::temp_dir()
+            .join(format!("csv-processing-{}-store-disk", std::process::id()));
+        let storage = DiskStorage::create(&path).unwrap();
+
+        store_roundtrip(Storage::<4>::open(&storage, ClientId(1)).unwrap()).unwrap();
+
+        let mut store: DiskStore<4> = storage.open(ClientId(2)).unwrap();
+        let mut other: DiskStore<4> = storage.open(ClientId(3)).unwrap();
+        let deposit = record(TransactionType::Deposit, FourDecimals::ONE);
+        store.insert(TxId(1_000_000), deposit).unwrap();
+        assert_eq!(other.get(TxId(1_000_000)).unwrap(), None);
+        assert_eq!(other.insert(TxId(1_000_000), deposit).unwrap_err().kind(),
+            io::ErrorKind::AlreadyExists);
+        assert_eq!(store.forget_below(TxId(1)).unwrap_err().kind(), io::ErrorKind::Unsupported);
+
+        let owners = |tx| Storage::<4>::owner(&storage, TxId(tx)).unwrap();
+        assert_eq!(owners(1_000_000), Some(ClientId(2)));
+        assert_eq!(owners(5), None);
+        assert!(Storage::<4>::claim(&storage, TxId(5), ClientId(3)).unwrap());
+        assert_eq!(owners(5), Some(ClientId(3)));
+        assert_eq!(other.get(TxId(5)).unwrap(), None);
+        other.insert(TxId(5), deposit).unwrap();
+        assert_eq!(other.len(), 1);
+        assert_eq!(other.get(TxId(5)).unwrap(), Some(deposit));
+
+        std::fs::remove_file(path).unwrap();
+    }
+    #[test]
+    pub fn disk_clients() {
+        use crate::{Clients, Transaction};
+
+        let path = |name: &str| std::env::temp_dir()
+            .join(format!("csv-processing-{}-store-{}", std::process::id(), name));
+        let mut memory = Clients::new();
+        let mut disk = Clients::with_storage(Default::default(),
+            DiskStorage::create(path("tx-amounts")).unwrap(),
+            DiskStorage::create(path("disputes")).unwrap());
+
+        for i in 0..1000u64 {
+            let tx = i as TxIdRepr;
+            let r#type = match tx % 4 {
+                0 | 1 => TransactionType::Deposit,
+                2 => TransactionType::Withdrawal,
+                _ => TransactionType::Dispute,
+            };
+            let amount = match r#type {
+                TransactionType::Dispute => None,
+                _ => Some(FourDecimals::new(i % 7, 0)),
+            };
+            let tx = if r#type == TransactionType::Dispute { tx - 3 } else { tx };
+            let transaction = Transaction {
+                r#type, client: ClientId((tx % 5) as ClientIdRepr), tx: TxId(tx), amount,
+            };
+            assert_eq!(disk.try_apply(transaction).unwrap(), memory.apply(transaction));
+        }
+        assert!(disk.sorted().into_iter().map(|client| (client.id(), client.total(), client.held()))
+            .eq(memory.sorted().into_iter().map(|client| (client.id(), client.total(), client.held()))));
+
+        // owners are kept in the storage file, collisions across clients are rejected
+        let deposit = Transaction {
+            r#type: TransactionType::Deposit, client: ClientId(9), tx: TxId(4),
+            amount: Some(FourDecimals::ONE),
+        };
+        assert_eq!(disk.try_apply(deposit).unwrap(), Err(crate::Rejection::DuplicateTransaction));
+        assert_eq!(disk.try_apply(deposit).unwrap(), memory.apply(deposit));
+
+        std::fs::remove_file(path("tx-amounts")).unwrap();
+        std::fs::remove_file(path("disputes")).unwrap();
+    }
+    #[test]
+    pub fn disk_out_of_range() {
+        use crate::{Clients, Transaction, Applied, Rejection};
+
+        let path = |name: &str| std::env::temp_dir()
+            .join(format!("csv-processing-{}-store-range-{}", std::process::id(), name));
+        let mut disk = Clients::with_storage(Default::default(),
+            DiskStorage::create(path("tx-amounts")).unwrap(),
+            DiskStorage::create(path("disputes")).unwrap());
+
+        for tx in [TxIdRepr::MAX, TxIdRepr::MAX - 1] {
+            let deposit = Transaction {
+                r#type: TransactionType::Deposit, client: ClientId(1), tx: TxId(tx),
+                amount: Some(FourDecimals::ONE),
+            };
+            let dispute = Transaction { r#type: TransactionType::Dispute, amount: None, ..deposit };
+            // rejected past the largest file offset or the file size limit, never a storage error
+            match disk.try_apply(deposit).unwrap() {
+                Ok(applied) => {
+                    assert_eq!(applied, Applied::Deposited);
+                    assert!(DiskStore::<4>::offset(TxId(tx)).is_some());
+                    assert_eq!(disk.try_apply(dispute).unwrap(), Ok(Applied::Disputed));
+                },
+                Err(rejection) => {
+                    assert_eq!(rejection, Rejection::TxIdOutOfRange);
+                    assert_eq!(disk.try_apply(dispute).unwrap(), Err(Rejection::UnknownTransaction));
+                },
+            }
+            if DiskStore::<4>::offset(TxId(tx)).is_none() {
+                assert_eq!(disk.try_apply(deposit).unwrap(), Err(Rejection::TxIdOutOfRange));
+            }
+        }
+
+        std::fs::remove_file(path("tx-amounts")).unwrap();
+        std::fs::remove_file(path("disputes")).unwrap();
+    }
+}
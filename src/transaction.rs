@@ -2,10 +2,11 @@
 
 use serde::{Deserialize, Serialize};
 
-use crate::four_decimals::FourDecimals;
+use crate::fixed_decimals::FixedDecimals;
+use crate::{ClientId, TxId, TransactionError};
 
 /// Type representation of a transaction type.
-#[derive(Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
 pub enum TransactionType {
     /// Deposit.
@@ -20,23 +21,146 @@ pub enum TransactionType {
     Chargeback,
 }
 
-/// Type representation of a transaction.
-#[derive(Debug, Deserialize, Serialize, PartialEq, Eq)]
-pub struct Transaction {
+/// Type representation of a transaction with `SCALE` decimals amount.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+pub struct Transaction<const SCALE: u32 = 4> {
     /// [TransactionType].
     pub r#type: TransactionType,
     /// Client id.
-    pub client: u16,
+    pub client: ClientId,
     /// Transaction number.
-    pub tx: u32,
+    pub tx: TxId,
     /// Amount or None.
-    pub amount: Option<FourDecimals>
+    pub amount: Option<FixedDecimals<SCALE>>
+}
+
+/// [Transaction] with an amount exactly when its [TransactionType] requires one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValidTransaction<const SCALE: u32 = 4> {
+    /// Deposit of a positive amount.
+    Deposit {
+        /// Client id.
+        client: ClientId,
+        /// Transaction number.
+        tx: TxId,
+        /// Positive amount.
+        amount: FixedDecimals<SCALE>,
+    },
+    /// Withdrawal of a positive amount.
+    Withdrawal {
+        /// Client id.
+        client: ClientId,
+        /// Transaction number.
+        tx: TxId,
+        /// Positive amount.
+        amount: FixedDecimals<SCALE>,
+    },
+    /// Dispute of a deposit or withdrawal.
+    Dispute {
+        /// Client id.
+        client: ClientId,
+        /// Disputed transaction number.
+        tx: TxId,
+    },
+    /// Resolve of a dispute.
+    Resolve {
+        /// Client id.
+        client: ClientId,
+        /// Disputed transaction number.
+        tx: TxId,
+    },
+    /// Chargeback of a dispute.
+    Chargeback {
+        /// Client id.
+        client: ClientId,
+        /// Disputed transaction number.
+        tx: TxId,
+    },
+}
+impl<const SCALE: u32> ValidTransaction<SCALE> {
+    /// [TransactionType].
+    pub fn r#type(&self) -> TransactionType {
+        match self {
+            ValidTransaction::Deposit { .. } => TransactionType::Deposit,
+            ValidTransaction::Withdrawal { .. } => TransactionType::Withdrawal,
+            ValidTransaction::Dispute { .. } => TransactionType::Dispute,
+            ValidTransaction::Resolve { .. } => TransactionType::Resolve,
+            ValidTransaction::Chargeback { .. } => TransactionType::Chargeback,
+        }
+    }
+    /// Client id.
+    pub fn client(&self) -> ClientId {
+        match *self {
+            ValidTransaction::Deposit { client, .. }
+            | ValidTransaction::Withdrawal { client, .. }
+            | ValidTransaction::Dispute { client, .. }
+            | ValidTransaction::Resolve { client, .. }
+            | ValidTransaction::Chargeback { client, .. } => client,
+        }
+    }
+    /// Transaction number.
+    pub fn tx(&self) -> TxId {
+        match *self {
+            ValidTransaction::Deposit { tx, .. }
+            | ValidTransaction::Withdrawal { tx, .. }
+            | ValidTransaction::Dispute { tx, .. }
+            | ValidTransaction::Resolve { tx, .. }
+            | ValidTransaction::Chargeback { tx, .. } => tx,
+        }
+    }
+    /// Amount of deposits and withdrawals.
+    pub fn amount(&self) -> Option<FixedDecimals<SCALE>> {
+        match *self {
+            ValidTransaction::Deposit { amount, .. }
+            | ValidTransaction::Withdrawal { amount, .. } => Some(amount),
+            _ => None,
+        }
+    }
+}
+impl<const SCALE: u32> TryFrom<Transaction<SCALE>> for ValidTransaction<SCALE> {
+    type Error = TransactionError;
+
+    fn try_from(transaction: Transaction<SCALE>) -> Result<Self, Self::Error> {
+        let Transaction { r#type, client, tx, amount } = transaction;
+        let amount = match (r#type, amount) {
+            (TransactionType::Deposit | TransactionType::Withdrawal, None) =>
+                return Err(TransactionError::MissingAmount),
+            (TransactionType::Deposit | TransactionType::Withdrawal, Some(amount)) => amount,
+            (_, Some(_)) => return Err(TransactionError::UnexpectedAmount),
+            (TransactionType::Dispute, None) => return Ok(ValidTransaction::Dispute { client, tx }),
+            (TransactionType::Resolve, None) => return Ok(ValidTransaction::Resolve { client, tx }),
+            (TransactionType::Chargeback, None) => return Ok(ValidTransaction::Chargeback { client, tx }),
+        };
+        if amount.is_negative() {
+            return Err(TransactionError::NegativeAmount)
+        }
+        if amount.is_zero() {
+            return Err(TransactionError::ZeroAmount)
+        }
+        if r#type == TransactionType::Deposit {
+            Ok(ValidTransaction::Deposit { client, tx, amount })
+        }
+        else {
+            Ok(ValidTransaction::Withdrawal { client, tx, amount })
+        }
+    }
+}
+impl<const SCALE: u32> From<ValidTransaction<SCALE>> for Transaction<SCALE> {
+    fn from(transaction: ValidTransaction<SCALE>) -> Self {
+        Self {
+            r#type: transaction.r#type(),
+            client: transaction.client(),
+            tx: transaction.tx(),
+            amount: transaction.amount(),
+        }
+    }
 }
 
 #[cfg(test)]
 pub mod test {
     use super::*;
 
+    use crate::FourDecimals;
     use std::io::Cursor;
     use csv::{ReaderBuilder, Writer, Trim};
 
@@ -51,10 +175,10 @@ pub mod test {
             .from_reader(Cursor::new(input));
         let result = rdr.deserialize().next().unwrap();
         let output: Transaction = result.unwrap();
-        let amount = FourDecimals { integer: 2, decimal: 0 };
+        let amount = FourDecimals::new(2, 0);
 
         assert_eq!(output, Transaction {
-            r#type: TransactionType::Deposit, client: 2, tx: 2, amount: Some(amount) })
+            r#type: TransactionType::Deposit, client: ClientId(2), tx: TxId(2), amount: Some(amount) })
     }
 
     #[test]
@@ -69,22 +193,22 @@ pub mod test {
 
         let result = rdr.deserialize().next().unwrap();
         let output: Transaction = result.unwrap();
-        let amount = FourDecimals { integer: 2, decimal: 0 };
+        let amount = FourDecimals::new(2, 0);
         assert_eq!(output, Transaction {
-            r#type: TransactionType::Deposit, client: 2, tx: 2, amount: Some(amount) });
+            r#type: TransactionType::Deposit, client: ClientId(2), tx: TxId(2), amount: Some(amount) });
 
         let result = rdr.deserialize().next().unwrap();
         let output: Transaction = result.unwrap();
         assert_eq!(output, Transaction {
-            r#type: TransactionType::Dispute, client: 2, tx: 2, amount: None });
+            r#type: TransactionType::Dispute, client: ClientId(2), tx: TxId(2), amount: None });
     }
 
     #[test]
     fn serialize_single() {
-        let amount = FourDecimals { integer: 1, decimal: 0 };
+        let amount = FourDecimals::new(1, 0);
         let transaction = Transaction {
             r#type: TransactionType::Withdrawal,
-            client: 2, tx: 10, amount: Some(amount),
+            client: ClientId(2), tx: TxId(10), amount: Some(amount),
         };
 
         let output = Vec::new();
@@ -98,14 +222,14 @@ pub mod test {
     }
     #[test]
     fn serialize_multiple() {
-        let amount = FourDecimals { integer: 1, decimal: 0 };
+        let amount = FourDecimals::new(1, 0);
         let transaction1 = Transaction {
             r#type: TransactionType::Withdrawal,
-            client: 2, tx: 10, amount: Some(amount),
+            client: ClientId(2), tx: TxId(10), amount: Some(amount),
         };
-        let transaction2 = Transaction {
+        let transaction2: Transaction = Transaction {
             r#type: TransactionType::Dispute,
-            client: 2, tx: 10, amount: None,
+            client: ClientId(2), tx: TxId(10), amount: None,
         };
 
         let output = Vec::new();
@@ -118,4 +242,24 @@ pub mod test {
         assert_eq!(String::from_utf8_lossy(&output),
           "type,client,tx,amount\nwithdrawal,2,10,1.0000\ndispute,2,10,\n");
     }
+    #[test]
+    pub fn validate() {
+        let transaction = |r#type, amount: Option<i64>| Transaction {
+            r#type, client: ClientId(2), tx: TxId(3), amount: amount.map(FourDecimals::from),
+        };
+        let deposit = ValidTransaction::try_from(transaction(TransactionType::Deposit, Some(1)));
+        assert_eq!(deposit, Ok(ValidTransaction::Deposit { client: ClientId(2), tx: TxId(3), amount: FourDecimals::from(1) }));
+        assert_eq!(Transaction::from(deposit.unwrap()), transaction(TransactionType::Deposit, Some(1)));
+        assert_eq!(ValidTransaction::try_from(transaction(TransactionType::Dispute, None)),
+            Ok(ValidTransaction::Dispute { client: ClientId(2), tx: TxId(3) }));
+
+        assert_eq!(ValidTransaction::try_from(transaction(TransactionType::Withdrawal, None)),
+            Err(TransactionError::MissingAmount));
+        assert_eq!(ValidTransaction::try_from(transaction(TransactionType::Chargeback, Some(1))),
+            Err(TransactionError::UnexpectedAmount));
+        assert_eq!(ValidTransaction::try_from(transaction(TransactionType::Deposit, Some(0))),
+            Err(TransactionError::ZeroAmount));
+        assert_eq!(ValidTransaction::try_from(transaction(TransactionType::Deposit, Some(-1))),
+            Err(TransactionError::NegativeAmount));
+    }
 }
@@ -0,0 +1,101 @@
+//! Property testing for [FourDecimals] arithmetic, conversions and rounding.
+//!
+//! - values are built from a sign, a u32 integral part and a decimal part
+//! - every [Rounding] mode is checked against the exact result
+
+use quickcheck::quickcheck;
+
+use csv_processing::{FourDecimals, Rounding};
+
+const ROUNDINGS: [Rounding; 5] = [
+    Rounding::HalfEven,
+    Rounding::HalfUp,
+    Rounding::Floor,
+    Rounding::Ceiling,
+    Rounding::Truncate,
+];
+
+fn new_value(negative: bool, integer: u32, decimal: u16) -> FourDecimals {
+    let value = FourDecimals::new(integer as u64, (decimal % 10000) as u64);
+    if negative { -value } else { value }
+}
+fn ulp() -> FourDecimals {
+    FourDecimals::new(0, 1)
+}
+
+quickcheck! {
+    fn mul_int_div_int(negative: bool, integer: u32, decimal: u16, factor: i32) -> bool {
+        let value = new_value(negative, integer, decimal);
+        let factor = if factor == 0 { 1 } else { factor as i64 };
+
+        let product = value.checked_mul_int(factor).unwrap();
+        ROUNDINGS.iter()
+            .all(|rounding| product.checked_div_int(factor, *rounding) == Some(value))
+    }
+
+    fn mul_one(negative: bool, integer: u32, decimal: u16) -> bool {
+        let value = new_value(negative, integer, decimal);
+        let one = FourDecimals::new(1, 0);
+
+        ROUNDINGS.iter()
+            .all(|rounding| value.checked_mul(one, *rounding) == Some(value))
+    }
+
+    fn mul_commutative(a: (bool, u32, u16), b: (bool, u32, u16)) -> bool {
+        let a = new_value(a.0, a.1, a.2);
+        let b = new_value(b.0, b.1, b.2);
+
+        ROUNDINGS.iter()
+            .all(|rounding| a.checked_mul(b, *rounding) == b.checked_mul(a, *rounding))
+    }
+
+    fn div_mul_integral(negative: bool, integer: u32, decimal: u16, divisor: i16) -> bool {
+        let value = new_value(negative, integer, decimal);
+        let divisor = if divisor == 0 { 1 } else { divisor as i64 };
+        let fixed_divisor = FourDecimals::new(divisor.unsigned_abs(), 0);
+        let fixed_divisor = if divisor < 0 { -fixed_divisor } else { fixed_divisor };
+
+        let product = value.checked_mul_int(divisor).unwrap();
+        ROUNDINGS.iter()
+            .all(|rounding| product.checked_div(fixed_divisor, *rounding) == Some(value))
+    }
+
+    fn div_int_rounding(negative: bool, integer: u32, decimal: u16, divisor: i16) -> bool {
+        let value = new_value(negative, integer, decimal);
+        let divisor = if divisor == 0 { 1 } else { divisor as i64 };
+        let quotient = |rounding| value.checked_div_int(divisor, rounding).unwrap();
+        // distance of the rounded quotient from the exact quotient, times divisor
+        let error = |rounding| (quotient(rounding).checked_mul_int(divisor).unwrap() - value).abs();
+
+        let floor = quotient(Rounding::Floor);
+        let ceiling = quotient(Rounding::Ceiling);
+        let truncate = quotient(Rounding::Truncate);
+        let bounds = ROUNDINGS.iter()
+            .all(|rounding| floor <= quotient(*rounding) && quotient(*rounding) <= ceiling);
+        let adjacent = ceiling == floor || ceiling == floor + ulp();
+        let towards_zero = truncate.abs() == floor.abs().min(ceiling.abs());
+        let exact = ulp().checked_mul_int(divisor.abs()).unwrap();
+        let nearest = [Rounding::HalfEven, Rounding::HalfUp].iter()
+            .all(|rounding| error(*rounding) * 2 <= exact);
+
+        bounds && adjacent && towards_zero && nearest
+    }
+
+    fn minor_units(negative: bool, integer: u32, decimal: u16) -> bool {
+        let value = new_value(negative, integer, decimal);
+        let units = value.to_minor_units();
+        let sign = if negative { -1 } else { 1 };
+
+        FourDecimals::from_minor_units(units) == Some(value)
+            && units == sign * (integer as i128 * 10000 + (decimal % 10000) as i128)
+    }
+
+    fn sum(values: Vec<(bool, u32, u16)>) -> bool {
+        let values: Vec<FourDecimals> = values.into_iter()
+            .map(|(negative, integer, decimal)| new_value(negative, integer, decimal))
+            .collect();
+        let units: i128 = values.iter().map(|value| value.to_minor_units()).sum();
+
+        values.iter().sum::<FourDecimals>() == FourDecimals::from_minor_units(units).unwrap()
+    }
+}
@@ -0,0 +1,431 @@
+//! End to end tests of the command line interface.
+
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("csv-processing-{}-{}", std::process::id(), name))
+}
+
+/// Rows of a rejects report sorted by line, rejections of different clients
+/// are written in any order with `--threads`.
+fn sorted_rejects(path: &PathBuf) -> String {
+    let report = fs::read_to_string(path).unwrap();
+    let mut lines: Vec<&str> = report.lines().collect();
+    lines[1..].sort_by_key(|line| line.split(',').next().unwrap().parse::<u64>().unwrap());
+    lines.iter().map(|line| format!("{}\n", line)).collect()
+}
+
+fn run(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_csv-processing"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn run_with_stdin(args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_csv-processing"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(stdin.as_bytes()).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+const INPUT: &str = "type, client, tx, amount\n\
+    deposit, 1, 1, 1.0\n\
+    deposit, 1, 2, 2.5\n\
+    withdrawal, 1, 3, 0.5\n";
+const OUTPUT: &str = "client,available,held,total,locked\n1,3.0000,0.0000,3.0000,false\n";
+
+#[test]
+fn stdin_dash() {
+    let output = run_with_stdin(&["-"], INPUT);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), OUTPUT);
+}
+
+#[test]
+fn stdin_no_argument() {
+    let output = run_with_stdin(&[], INPUT);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), OUTPUT);
+}
+
+#[test]
+fn output_file() {
+    let path = temp_path("output-file.csv");
+
+    let output = run_with_stdin(&["--output", path.to_str().unwrap()], INPUT);
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+    assert_eq!(fs::read_to_string(&path).unwrap(), OUTPUT);
+
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn sorted_output() {
+    let input = "type, client, tx, amount\n\
+        deposit, 3, 1, 1.0\n\
+        deposit, 1, 2, 3.0\n\
+        deposit, 2, 3, 2.0\n";
+
+    let output = run_with_stdin(&[], input);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout),
+        "client,available,held,total,locked\n\
+        1,3.0000,0.0000,3.0000,false\n\
+        2,2.0000,0.0000,2.0000,false\n\
+        3,1.0000,0.0000,1.0000,false\n");
+
+    let output = run_with_stdin(&["--sort", "total"], input);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout),
+        "client,available,held,total,locked\n\
+        3,1.0000,0.0000,1.0000,false\n\
+        2,2.0000,0.0000,2.0000,false\n\
+        1,3.0000,0.0000,3.0000,false\n");
+
+    let output = run(&["--sort", "name"]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn rejects_report() {
+    let input = temp_path("rejects-input.csv");
+    let rejects = temp_path("rejects-output.csv");
+    fs::write(&input, "type, client, tx, amount\n\
+        deposit, 1, 1, 1.0\n\
+        withdrawal, 1, 2, 2.0\n\
+        dispute, 1, 3,\n\
+        withdrawal, 1, 4, 0.5\n\
+        dispute, 2, 1,\n").unwrap();
+
+    let output = run(&[input.to_str().unwrap(), "--rejects", rejects.to_str().unwrap()]);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout),
+        "client,available,held,total,locked\n1,0.5000,0.0000,0.5000,false\n");
+    assert_eq!(fs::read_to_string(&rejects).unwrap(),
+        "line,type,client,tx,amount,reason\n\
+        3,withdrawal,1,2,2.0000,insufficient_funds\n\
+        4,dispute,1,3,,unknown_transaction\n\
+        6,dispute,2,1,,client_mismatch\n");
+
+    fs::remove_file(input).unwrap();
+    fs::remove_file(rejects).unwrap();
+}
+
+#[test]
+fn threads() {
+    let rejects = temp_path("threads-rejects.csv");
+    let threads_rejects = temp_path("threads-rejects-parallel.csv");
+    let mut input = String::from("type,client,tx,amount\n");
+    for tx in 0..5000 {
+        let client = tx * 7 % 31;
+        match tx % 4 {
+            0 | 1 => input += &format!("deposit,{},{},{}.{}\n", client, tx, 1 + tx % 13, tx % 10),
+            2 => input += &format!("withdrawal,{},{},{}\n", client, tx, 1 + tx % 29),
+            _ => input += &format!("dispute,{},{},\n", client, tx - 3),
+        }
+    }
+
+    let output = run_with_stdin(&["--rejects", rejects.to_str().unwrap()], &input);
+    let threads_output = run_with_stdin(
+        &["--threads", "4", "--rejects", threads_rejects.to_str().unwrap()], &input);
+    assert!(output.status.success() && threads_output.status.success());
+    assert_eq!(output.stdout, threads_output.stdout);
+    assert_eq!(sorted_rejects(&rejects), sorted_rejects(&threads_rejects));
+
+    fs::remove_file(rejects).unwrap();
+    fs::remove_file(threads_rejects).unwrap();
+}
+
+#[test]
+fn retention() {
+    let rejects = temp_path("retention-rejects.csv");
+    let input = "type, client, tx, amount\n\
+        deposit, 1, 1, 1.0\n\
+        deposit, 1, 2, 2.0\n\
+        deposit, 1, 3, 3.0\n\
+        dispute, 1, 1,\n\
+        dispute, 1, 2,\n";
+
+    let output = run_with_stdin(
+        &["--retain-entries", "2", "--rejects", rejects.to_str().unwrap()], input);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout),
+        "client,available,held,total,locked\n1,4.0000,2.0000,6.0000,false\n");
+    assert_eq!(fs::read_to_string(&rejects).unwrap(),
+        "line,type,client,tx,amount,reason\n\
+        5,dispute,1,1,,expired\n");
+
+    let output = run(&["--retain-age", "-1"]);
+    assert!(!output.status.success());
+
+    // a forgotten id is reused by another client, with threads like without
+    let input = "type, client, tx, amount\n\
+        deposit, 1, 5, 1.0\n\
+        deposit, 1, 6, 1.0\n\
+        deposit, 2, 5, 1.0\n\
+        dispute, 1, 5,\n";
+    for threads in ["1", "2"] {
+        let output = run_with_stdin(&["--threads", threads, "--retain-entries", "1",
+            "--rejects", rejects.to_str().unwrap()], input);
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8_lossy(&output.stdout),
+            "client,available,held,total,locked\n\
+            1,2.0000,0.0000,2.0000,false\n\
+            2,1.0000,0.0000,1.0000,false\n");
+        assert_eq!(fs::read_to_string(&rejects).unwrap(),
+            "line,type,client,tx,amount,reason\n\
+            5,dispute,1,5,,client_mismatch\n");
+    }
+
+    fs::remove_file(rejects).unwrap();
+}
+
+#[test]
+fn duplicate_transactions() {
+    let rejects = temp_path("duplicate-rejects.csv");
+    let input = "type, client, tx, amount\n\
+        deposit, 1, 1, 1.0\n\
+        deposit, 2, 1, 1.0\n\
+        deposit, 1, 1, 1.0\n\
+        deposit, 1, 1, 2.0\n";
+
+    for threads in ["1", "2"] {
+        let output = run_with_stdin(&["--threads", threads, "--idempotent-replays",
+            "--rejects", rejects.to_str().unwrap()], input);
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8_lossy(&output.stdout),
+            "client,available,held,total,locked\n1,1.0000,0.0000,1.0000,false\n");
+        assert_eq!(fs::read_to_string(&rejects).unwrap(),
+            "line,type,client,tx,amount,reason\n\
+            3,deposit,2,1,1.0000,duplicate_transaction\n\
+            5,deposit,1,1,2.0000,duplicate_transaction\n");
+    }
+
+    let output = run_with_stdin(&["--rejects", rejects.to_str().unwrap()], input);
+    assert!(output.status.success());
+    assert_eq!(fs::read_to_string(&rejects).unwrap(),
+        "line,type,client,tx,amount,reason\n\
+        3,deposit,2,1,1.0000,duplicate_transaction\n\
+        4,deposit,1,1,1.0000,duplicate_transaction\n\
+        5,deposit,1,1,2.0000,duplicate_transaction\n");
+
+    fs::remove_file(rejects).unwrap();
+}
+
+#[test]
+fn redispute() {
+    let input = "type, client, tx, amount\n\
+        deposit, 1, 1, 1.0\n\
+        dispute, 1, 1,\n\
+        resolve, 1, 1,\n\
+        dispute, 1, 1,\n";
+
+    let output = run_with_stdin(&[], input);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout),
+        "client,available,held,total,locked\n1,1.0000,0.0000,1.0000,false\n");
+
+    let output = run_with_stdin(&["--redispute", "resolved"], input);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout),
+        "client,available,held,total,locked\n1,0.0000,1.0000,1.0000,false\n");
+}
+
+#[test]
+fn limits() {
+    let limits = temp_path("limits.csv");
+    let rejects = temp_path("limits-rejects.csv");
+    fs::write(&limits, "client, overdraft, max_withdrawal, max_daily_withdrawal\n\
+        1, 2.0, 2.5, \n\
+        2, , , 1.0\n").unwrap();
+    let input = "type, client, tx, amount\n\
+        deposit, 1, 1, 1.0\n\
+        withdrawal, 1, 2, 3.0\n\
+        withdrawal, 1, 3, 2.5\n\
+        withdrawal, 1, 4, 1.0\n\
+        deposit, 2, 5, 5.0\n\
+        withdrawal, 2, 6, 1.0\n\
+        withdrawal, 2, 7, 1.0\n";
+
+    for threads in ["1", "2"] {
+        let output = run_with_stdin(&["--limits", limits.to_str().unwrap(),
+            "--rejects", rejects.to_str().unwrap(), "--threads", threads], input);
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8_lossy(&output.stdout),
+            "client,available,held,total,locked\n\
+            1,-1.5000,0.0000,-1.5000,false\n\
+            2,4.0000,0.0000,4.0000,false\n");
+        assert_eq!(sorted_rejects(&rejects),
+            "line,type,client,tx,amount,reason\n\
+            3,withdrawal,1,2,3.0000,withdrawal_limit_exceeded\n\
+            5,withdrawal,1,4,1.0000,overdraft_exceeded\n\
+            8,withdrawal,2,7,1.0000,daily_limit_exceeded\n");
+    }
+
+    // a new date starts a new day of the daily limit
+    let input = "type, client, tx, amount, date\n\
+        deposit, 2, 1, 5.0, 2024-01-01\n\
+        withdrawal, 2, 2, 1.0, 2024-01-01\n\
+        withdrawal, 2, 3, 1.0, 2024-01-01\n\
+        withdrawal, 2, 4, 1.0, 2024-01-02\n\
+        withdrawal, 2, 5, 1.0, 2024-01-02\n";
+    for threads in ["1", "2"] {
+        let output = run_with_stdin(&["--limits", limits.to_str().unwrap(),
+            "--rejects", rejects.to_str().unwrap(), "--threads", threads], input);
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8_lossy(&output.stdout),
+            "client,available,held,total,locked\n2,3.0000,0.0000,3.0000,false\n");
+        assert_eq!(fs::read_to_string(&rejects).unwrap(),
+            "line,type,client,tx,amount,reason\n\
+            4,withdrawal,2,3,1.0000,daily_limit_exceeded\n\
+            6,withdrawal,2,5,1.0000,daily_limit_exceeded\n");
+    }
+
+    fs::write(&limits, "client, overdraft, max_withdrawal, max_daily_withdrawal\n1, -1.0, , \n").unwrap();
+    let output = run(&["--limits", limits.to_str().unwrap()]);
+    assert!(!output.status.success());
+
+    fs::remove_file(limits).unwrap();
+    fs::remove_file(rejects).unwrap();
+}
+#[test]
+fn malformed_rows() {
+    let errors = temp_path("malformed-errors.csv");
+    let input = "type, client, tx, amount\n\
+        deposit, 1, 1, 1.0\n\
+        deposit, 1, 2, 1.00001\n\
+        transfer, 1, 3, 1.0\n\
+        deposit, 1, 4, 2.0\n";
+
+    let output = run_with_stdin(&[], input);
+    assert_eq!(output.status.code(), Some(65));
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .starts_with("Error [parse] : Parse Error : record 2 (line 3, byte 44) field amount value \"1.00001\": "));
+    let output = run_with_stdin(&["--on-error", "fail"], input);
+    assert_eq!(output.status.code(), Some(65));
+
+    let output = run_with_stdin(&["--on-error", "skip", "--errors", errors.to_str().unwrap()], input);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout),
+        "client,available,held,total,locked\n1,3.0000,0.0000,3.0000,false\n");
+    let report = fs::read_to_string(&errors).unwrap();
+    let lines: Vec<_> = report.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert_eq!(lines[0], "record,line,byte,field,value,error");
+    assert_eq!(lines[1], "2,3,44,amount,1.00001,Too many decimals.");
+    assert!(lines[2].starts_with("3,4,67,type,transfer,"));
+
+    let output = run_with_stdin(&["--max-errors", "2"], input);
+    assert!(output.status.success());
+    // the mode and the maximum do not depend on their order
+    for args in [["--max-errors", "1", "--on-error", "skip"], ["--on-error", "skip", "--max-errors", "1"]] {
+        let mut args = args.to_vec();
+        args.extend(["--errors", errors.to_str().unwrap()]);
+        let output = run_with_stdin(&args, input);
+        assert_eq!(output.status.code(), Some(65));
+        assert!(String::from_utf8_lossy(&output.stderr).starts_with("Error [too_many_errors] : "));
+        // the row crossing the maximum is reported
+        assert_eq!(fs::read_to_string(&errors).unwrap().lines().count(), 3);
+    }
+    let output = run(&["--max-errors", "1", "--on-error", "fail"]);
+    assert_eq!(output.status.code(), Some(64));
+    let output = run(&["--on-error", "fail", "--max-errors", "1"]);
+    assert_eq!(output.status.code(), Some(64));
+
+    fs::remove_file(errors).unwrap();
+}
+#[test]
+fn invalid_amounts() {
+    let rejects = temp_path("invalid-amounts-rejects.csv");
+    // invalid amounts are rejected without failing the run, a corrected retry can use their id
+    let input = "type, client, tx, amount\n\
+        deposit, 1, 5, 0\n\
+        deposit, 1, 6,\n\
+        deposit, 1, 5, 10\n";
+    for threads in ["1", "2"] {
+        let output = run_with_stdin(
+            &["--threads", threads, "--rejects", rejects.to_str().unwrap()], input);
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8_lossy(&output.stdout),
+            "client,available,held,total,locked\n1,10.0000,0.0000,10.0000,false\n");
+        assert_eq!(sorted_rejects(&rejects),
+            "line,type,client,tx,amount,reason\n\
+            2,deposit,1,5,0.0000,zero_amount\n\
+            3,deposit,1,6,,missing_amount\n");
+    }
+
+    fs::remove_file(rejects).unwrap();
+}
+#[test]
+fn io_error() {
+    let output = run(&[temp_path("missing.csv").to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(74));
+    assert!(String::from_utf8_lossy(&output.stderr).starts_with("Error [io] : "));
+}
+#[test]
+fn wide_client_ids() {
+    let input = "type, client, tx, amount\n\
+        deposit, 70000, 1, 1.0\n\
+        deposit, 4000000000, 2, 2.0\n\
+        deposit, 1, 3, 3.0\n";
+
+    for threads in ["1", "2"] {
+        let output = run_with_stdin(&["--threads", threads], input);
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8_lossy(&output.stdout),
+            "client,available,held,total,locked\n\
+            1,3.0000,0.0000,3.0000,false\n\
+            70000,1.0000,0.0000,1.0000,false\n\
+            4000000000,2.0000,0.0000,2.0000,false\n");
+    }
+}
+#[test]
+fn disk_store() {
+    let store = temp_path("store");
+    fs::create_dir_all(&store).unwrap();
+    let input = "type, client, tx, amount\n\
+        deposit, 1, 1, 1.0\n\
+        deposit, 2, 2, 2.0\n\
+        deposit, 2, 1, 5.0\n\
+        dispute, 1, 1,\n\
+        dispute, 2, 1,\n\
+        chargeback, 1, 1,\n";
+
+    // a transaction id of another client is rejected, as in memory
+    let memory = run_with_stdin(&[], input);
+    for threads in ["1", "2"] {
+        let output = run_with_stdin(
+            &["--store", store.to_str().unwrap(), "--threads", threads], input);
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8_lossy(&output.stdout),
+            "client,available,held,total,locked\n\
+            1,0.0000,0.0000,0.0000,true\n\
+            2,2.0000,0.0000,2.0000,false\n");
+        assert_eq!(output.stdout, memory.stdout);
+    }
+
+    let output = run(&["--store", store.to_str().unwrap(), "--retain-age", "10"]);
+    assert!(!output.status.success());
+
+    fs::remove_dir_all(store).unwrap();
+}
+
+#[test]
+fn wrong_arguments() {
+    let output = run(&["a.csv", "b.csv"]);
+    assert_eq!(output.status.code(), Some(64));
+    let output = run(&["--output"]);
+    assert!(!output.status.success());
+    let output = run(&["--threads", "0"]);
+    assert!(!output.status.success());
+}
@@ -1,216 +1,317 @@
 //! Model testing for [Client] [Transaction] processing.
 //!
 //! - single client under test
-//! - transactions have integral only values (decimal = 0)
+//! - transactions have positive integral only values (decimal = 0)
 
 use quickcheck::quickcheck;
 
-use csv_processing::{FourDecimals, Client, Transaction, TransactionType};
+use csv_processing::{FourDecimals, Client, Transaction, TransactionType, ClientId, TxId, TxIdRepr};
+use csv_processing::{Applied, Rejection, Policy, WithdrawalDisputes};
 
-fn new_deposit(id: u16, tx: u32) -> Transaction {
+fn new_deposit(id: ClientId, tx: TxIdRepr) -> Transaction {
     Transaction {
         r#type: TransactionType::Deposit,
         client: id,
-        tx,
-        amount: Some(FourDecimals {
-            integer: rand::random::<u8>() as u64,
-            decimal: 0,
-        }),
+        tx: TxId(tx),
+        amount: Some(FourDecimals::new(1 + rand::random::<u8>() as u64, 0)),
     }
 }
-fn new_withdrawal(id: u16, tx: u32, max: u64) -> Transaction {
+/// Withdrawal of at most `max`, or of 1 when `max` is 0.
+fn new_withdrawal(id: ClientId, tx: TxIdRepr, max: u64) -> Transaction {
     Transaction {
         r#type: TransactionType::Withdrawal,
         client: id,
-        tx,
-        amount: Some(FourDecimals {
-            integer: (rand::random::<u8>() as u64) % (max + 1),
-            decimal: 0,
-        }),
+        tx: TxId(tx),
+        amount: Some(FourDecimals::new(1 + (rand::random::<u8>() as u64) % max.max(1), 0)),
     }
 }
 
+/// Deposit and withdraw, dispute every withdrawal, resolve every other dispute
+/// and charge back the last unresolved one.
+fn withdrawal_disputes(mode: WithdrawalDisputes, rounds: u8) -> bool {
+    let client_id = ClientId(0);
+
+    let policy = Policy { withdrawal_disputes: mode, ..Policy::default() };
+    let mut client = Client::new(client_id);
+    let mut model_available: i64 = 0;
+    let mut model_held: i64 = 0;
+    let mut unresolved = None;
+    let mut outcomes = true;
+
+    for (tx, i) in (0..).step_by(2).zip(0..rounds) {
+        let deposit = new_deposit(client_id, tx);
+        model_available += deposit.amount.unwrap().integer() as i64;
+        outcomes &= client.apply_with(&policy, deposit) == Ok(Applied::Deposited);
+
+        let withdrawal = new_withdrawal(client_id, tx + 1, model_available.max(0) as u64);
+        let amount = withdrawal.amount.unwrap().integer() as i64;
+        // held disputes of withdrawals can leave the available funds negative
+        if amount > model_available {
+            outcomes &= client.apply_with(&policy, withdrawal) == Err(Rejection::InsufficientFunds);
+            continue;
+        }
+        model_available -= amount;
+        outcomes &= client.apply_with(&policy, withdrawal) == Ok(Applied::Withdrawn);
+
+        let dispute = Transaction {
+            r#type: TransactionType::Dispute,
+            client: client_id, tx: TxId(tx + 1), amount: None,
+        };
+        let resolve = Transaction {
+            r#type: TransactionType::Resolve,
+            client: client_id, tx: TxId(tx + 1), amount: None,
+        };
+        match mode {
+            WithdrawalDisputes::Hold | WithdrawalDisputes::ReverseCredit => {
+                outcomes &= client.apply_with(&policy, dispute) == Ok(Applied::Disputed);
+                if mode == WithdrawalDisputes::Hold {
+                    model_available -= amount;
+                }
+                model_held += amount;
+
+                if i % 2 == 0 {
+                    outcomes &= client.apply_with(&policy, resolve) == Ok(Applied::Resolved);
+                    if mode == WithdrawalDisputes::Hold {
+                        model_available += amount;
+                    }
+                    model_held -= amount;
+                }
+                else {
+                    unresolved = Some((tx + 1, amount));
+                }
+            },
+            WithdrawalDisputes::Ignore => {
+                outcomes &= client.apply_with(&policy, dispute) == Ok(Applied::Ignored);
+                outcomes &= client.apply_with(&policy, resolve) == Err(Rejection::NotDisputed);
+            },
+            WithdrawalDisputes::Reject => {
+                outcomes &= client.apply_with(&policy, dispute) == Err(Rejection::WithdrawalDispute);
+                outcomes &= client.apply_with(&policy, resolve) == Err(Rejection::NotDisputed);
+            },
+        }
+    }
+
+    if let Some((tx, amount)) = unresolved {
+        let chargeback = Transaction {
+            r#type: TransactionType::Chargeback,
+            client: client_id, tx: TxId(tx), amount: None,
+        };
+        outcomes &= client.apply_with(&policy, chargeback) == Ok(Applied::ChargedBack);
+        if mode == WithdrawalDisputes::ReverseCredit {
+            model_available += amount;
+        }
+        model_held -= amount;
+    }
+
+    outcomes
+        && (client.locked() == unresolved.is_some())
+        && (client.available() == FourDecimals::from(model_available))
+        && (client.held() == FourDecimals::from(model_held))
+        && (client.total() == FourDecimals::from(model_available + model_held))
+}
+
 quickcheck! {
     fn deposits_withdrawals(rounds: u8) -> bool {
         let mut tx = 0;
-        let client_id = 0;
+        let client_id = ClientId(0);
 
         let mut client = Client::new(client_id);
         let mut model = 0;
+        let mut outcomes = true;
 
         for _ in 0..rounds {
             let deposit = new_deposit(client_id, tx);
             tx += 1;
-            model += deposit.amount.unwrap().integer;
-            client.apply(deposit);
+            model += deposit.amount.unwrap().integer();
+            outcomes &= client.apply(deposit) == Ok(Applied::Deposited);
 
             let withdrawal = new_withdrawal(client_id, tx, 10000);
             tx += 1;
-            let amount = withdrawal.amount.unwrap().integer;
-            if amount <= model {
+            let amount = withdrawal.amount.unwrap().integer();
+            let expected = if amount <= model {
                 model -= amount;
+                Ok(Applied::Withdrawn)
             }
-            client.apply(withdrawal);
+            else {
+                Err(Rejection::InsufficientFunds)
+            };
+            outcomes &= client.apply(withdrawal) == expected;
         }
 
-        (client.total().integer == model)
-            && (client.available().integer == model)
-            && (client.held().integer == 0)
+        outcomes
+            && (client.total().integer() == model)
+            && (client.available().integer() == model)
+            && (client.held().integer() == 0)
     }
 
     fn deposits_undisputed(rounds: u8) -> bool {
-        let mut tx = 0;
-        let client_id = 0;
+        let client_id = ClientId(0);
 
         let mut client = Client::new(client_id);
         let mut model = 0;
+        let mut outcomes = true;
 
-        for i in 0..rounds {
+        for (tx, i) in (0..).zip(0..rounds) {
             let deposit = new_deposit(client_id, tx);
             let resolve = Transaction {
                 r#type: TransactionType::Resolve,
                 client: client_id,
-                tx,
+                tx: TxId(tx),
                 amount: None,
             };
             let chargeback = Transaction {
                 r#type: TransactionType::Chargeback,
                 client: client_id,
-                tx,
+                tx: TxId(tx),
                 amount: None,
             };
-            tx += 1;
 
-            model += deposit.amount.unwrap().integer;
-            client.apply(deposit);
+            model += deposit.amount.unwrap().integer();
+            outcomes &= client.apply(deposit) == Ok(Applied::Deposited);
             if i % 2 == 0 {
-                client.apply(resolve);
-                client.apply(chargeback);
+                outcomes &= client.apply(resolve) == Err(Rejection::NotDisputed);
+                outcomes &= client.apply(chargeback) == Err(Rejection::NotDisputed);
             }
             else {
-                client.apply(chargeback);
-                client.apply(resolve);
+                outcomes &= client.apply(chargeback) == Err(Rejection::NotDisputed);
+                outcomes &= client.apply(resolve) == Err(Rejection::NotDisputed);
             }
         }
 
-        (client.total().integer == model)
-            && (client.available().integer == model)
-            && (client.held().integer == 0)
+        outcomes
+            && (client.total().integer() == model)
+            && (client.available().integer() == model)
+            && (client.held().integer() == 0)
     }
 
     fn deposits_disputes(rounds: u8) -> bool {
-        let mut tx = 0;
-        let client_id = 0;
+        let client_id = ClientId(0);
 
         let mut client = Client::new(client_id);
         let mut model_available = 0;
         let mut model_held = 0;
         let mut model_total = 0;
+        let mut outcomes = true;
 
-        for i in 0..rounds {
+        for (tx, i) in (0..).zip(0..rounds) {
             let deposit = new_deposit(client_id, tx);
             let dispute = Transaction {
                 r#type: TransactionType::Dispute,
-                client: client_id, tx, amount: None,
+                client: client_id, tx: TxId(tx), amount: None,
             };
-            tx += 1;
 
-            let amount = deposit.amount.unwrap().integer;
+            let amount = deposit.amount.unwrap().integer();
             model_available += amount;
             model_total += amount;
-            client.apply(deposit);
+            outcomes &= client.apply(deposit) == Ok(Applied::Deposited);
 
             if i % 2 == 0 {
-                client.apply(dispute);
+                outcomes &= client.apply(dispute) == Ok(Applied::Disputed);
                 model_available -= amount;
                 model_held += amount;
             }
         }
 
-        (client.available().integer == model_available)
-            && (client.held().integer == model_held)
-            && (client.total().integer == model_total)
+        outcomes
+            && (client.available().integer() == model_available)
+            && (client.held().integer() == model_held)
+            && (client.total().integer() == model_total)
     }
     fn deposits_disputes_resolves(rounds: u8) -> bool {
-        let mut tx = 0;
-        let client_id = 0;
+        let client_id = ClientId(0);
 
         let mut client = Client::new(client_id);
         let mut model_available = 0;
         let mut model_held = 0;
         let mut model_total = 0;
+        let mut outcomes = true;
 
-        for i in 0..rounds {
+        for (tx, i) in (0..).zip(0..rounds) {
             let deposit = new_deposit(client_id, tx);
             let dispute = Transaction {
                 r#type: TransactionType::Dispute,
-                client: client_id, tx, amount: None,
+                client: client_id, tx: TxId(tx), amount: None,
             };
             let resolve = Transaction {
                 r#type: TransactionType::Resolve,
-                client: client_id, tx, amount: None,
+                client: client_id, tx: TxId(tx), amount: None,
             };
-            tx += 1;
 
-            let amount = deposit.amount.unwrap().integer;
+            let amount = deposit.amount.unwrap().integer();
             model_available += amount;
             model_total += amount;
-            client.apply(deposit);
+            outcomes &= client.apply(deposit) == Ok(Applied::Deposited);
 
             if i % 2 == 0 {
-                client.apply(dispute);
+                outcomes &= client.apply(dispute) == Ok(Applied::Disputed);
                 model_available -= amount;
                 model_held += amount;
 
                 if i % 3 == 0 {
-                    client.apply(resolve);
+                    outcomes &= client.apply(resolve) == Ok(Applied::Resolved);
                     model_available += amount;
                     model_held -= amount;
                 }
             }
         }
 
-        (client.available().integer == model_available)
-            && (client.held().integer == model_held)
-            && (client.total().integer == model_total)
+        outcomes
+            && (client.available().integer() == model_available)
+            && (client.held().integer() == model_held)
+            && (client.total().integer() == model_total)
     }
 
     fn chargeback(rounds: u8) -> bool {
         let mut tx = 0;
-        let client_id = 0;
+        let client_id = ClientId(0);
 
         let mut client = Client::new(client_id);
         let mut model = 0;
+        let mut outcomes = true;
 
         let deposit = new_deposit(client_id, tx);
         let dispute = Transaction {
             r#type: TransactionType::Dispute,
-            client: client_id, tx, amount: None,
+            client: client_id, tx: TxId(tx), amount: None,
         };
         let chargeback = Transaction {
             r#type: TransactionType::Chargeback,
-            client: client_id, tx, amount: None,
+            client: client_id, tx: TxId(tx), amount: None,
         };
         tx += 1;
 
-        client.apply(deposit);
-        client.apply(dispute);
-        client.apply(chargeback);
+        outcomes &= client.apply(deposit) == Ok(Applied::Deposited);
+        outcomes &= client.apply(dispute) == Ok(Applied::Disputed);
+        outcomes &= client.apply(chargeback) == Ok(Applied::ChargedBack);
 
         for _ in 0..rounds {
             let deposit = new_deposit(client_id, tx);
             tx += 1;
-            model += deposit.amount.unwrap().integer;
-            client.apply(deposit);
+            model += deposit.amount.unwrap().integer();
+            outcomes &= client.apply(deposit) == Err(Rejection::Locked);
 
             let withdrawal = new_withdrawal(client_id, tx, model);
             tx += 1;
-            model -= withdrawal.amount.unwrap().integer;
-            client.apply(withdrawal);
+            model -= withdrawal.amount.unwrap().integer();
+            outcomes &= client.apply(withdrawal) == Err(Rejection::Locked);
         }
 
-        (client.total().integer == 0)
-            && (client.available().integer == 0)
-            && (client.held().integer == 0)
+        outcomes
+            && (client.total().integer() == 0)
+            && (client.available().integer() == 0)
+            && (client.held().integer() == 0)
+    }
+
+    fn withdrawal_disputes_hold(rounds: u8) -> bool {
+        withdrawal_disputes(WithdrawalDisputes::Hold, rounds)
+    }
+    fn withdrawal_disputes_ignore(rounds: u8) -> bool {
+        withdrawal_disputes(WithdrawalDisputes::Ignore, rounds)
+    }
+    fn withdrawal_disputes_reject(rounds: u8) -> bool {
+        withdrawal_disputes(WithdrawalDisputes::Reject, rounds)
+    }
+    fn withdrawal_disputes_reverse_credit(rounds: u8) -> bool {
+        withdrawal_disputes(WithdrawalDisputes::ReverseCredit, rounds)
     }
 }